@@ -161,19 +161,24 @@ public final class Dtos {
             @NotNull Double y,
             @Size(max = 200) String title,
             // Markdown body (rich text + image/video embeds); generous cap.
-            @Size(max = 50000) String description
+            @Size(max = 50000) String description,
+            // Visible zoom range, inclusive; optional, null = unbounded.
+            @PositiveOrZero Integer minZoom,
+            @PositiveOrZero Integer maxZoom
     ) {}
 
     public record MarkerResponse(
             long id, long mapId, long categoryId,
             double x, double y,
-            String title, String description
+            String title, String description,
+            Integer minZoom, Integer maxZoom
     ) {
         public static MarkerResponse from(Marker m) {
             return new MarkerResponse(
                     m.getId(), m.getMapId(), m.getCategoryId(),
                     m.getGeom().getX(), m.getGeom().getY(),
-                    m.getTitle(), m.getDescription()
+                    m.getTitle(), m.getDescription(),
+                    m.getMinZoom(), m.getMaxZoom()
             );
         }
     }
@@ -183,7 +188,9 @@ public final class Dtos {
             @NotNull Double x,
             @NotNull Double y,
             String title,
-            String description
+            String description,
+            @PositiveOrZero Integer minZoom,
+            @PositiveOrZero Integer maxZoom
     ) {}
 
     public record BulkImportRequest(@NotNull List<BulkImportRow> markers) {}
@@ -32,7 +32,7 @@ public class MarkerController {
             @PathVariable long mapId,
             @Valid @RequestBody MarkerRequest req) {
         var saved = markers.create(mapId, req.categoryId(), req.x(), req.y(),
-                req.title(), req.description());
+                req.title(), req.description(), req.minZoom(), req.maxZoom());
         return ResponseEntity
                 .created(URI.create("/api/v1/markers/" + saved.getId()))
                 .body(MarkerResponse.from(saved));
@@ -41,7 +41,7 @@ public class MarkerController {
     @PutMapping("/markers/{id}")
     public MarkerResponse update(@PathVariable long id, @Valid @RequestBody MarkerRequest req) {
         var m = markers.update(id, req.categoryId(), req.x(), req.y(),
-                req.title(), req.description());
+                req.title(), req.description(), req.minZoom(), req.maxZoom());
         return MarkerResponse.from(m);
     }
 
@@ -57,7 +57,8 @@ public class MarkerController {
                                    @Valid @RequestBody BulkImportRequest req) {
         var rows = req.markers().stream()
                 .map(r -> new MarkerInsert(
-                        mapId, r.categoryId(), r.x(), r.y(), r.title(), r.description()))
+                        mapId, r.categoryId(), r.x(), r.y(), r.title(), r.description(),
+                        r.minZoom(), r.maxZoom()))
                 .toList();
         int n = markers.bulkImport(mapId, rows);
         return new BulkImportResponse(n);
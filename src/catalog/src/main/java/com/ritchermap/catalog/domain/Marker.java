@@ -27,6 +27,13 @@ public class Marker {
     @Column(nullable = false, columnDefinition = "geometry(Point,0)")
     private Point geom;
 
+    /** Visible zoom range, inclusive (V4); null = unbounded on that side. */
+    @Column(name = "min_zoom")
+    private Integer minZoom;
+
+    @Column(name = "max_zoom")
+    private Integer maxZoom;
+
     @Column(name = "created_at", nullable = false, updatable = false, insertable = false)
     private Instant createdAt;
 
@@ -35,12 +42,15 @@ public class Marker {
 
     protected Marker() {}
 
-    public Marker(Long mapId, Long categoryId, Point geom, String title, String description) {
+    public Marker(Long mapId, Long categoryId, Point geom, String title, String description,
+                  Integer minZoom, Integer maxZoom) {
         this.mapId = mapId;
         this.categoryId = categoryId;
         this.geom = geom;
         this.title = title;
         this.description = description;
+        this.minZoom = minZoom;
+        this.maxZoom = maxZoom;
     }
 
     public Long getId() { return id; }
@@ -49,13 +59,18 @@ public class Marker {
     public String getTitle() { return title; }
     public String getDescription() { return description; }
     public Point getGeom() { return geom; }
+    public Integer getMinZoom() { return minZoom; }
+    public Integer getMaxZoom() { return maxZoom; }
     public Instant getCreatedAt() { return createdAt; }
     public Instant getUpdatedAt() { return updatedAt; }
 
-    public void update(Long categoryId, Point geom, String title, String description) {
+    public void update(Long categoryId, Point geom, String title, String description,
+                       Integer minZoom, Integer maxZoom) {
         this.categoryId = categoryId;
         this.geom = geom;
         this.title = title;
         this.description = description;
+        this.minZoom = minZoom;
+        this.maxZoom = maxZoom;
     }
 }
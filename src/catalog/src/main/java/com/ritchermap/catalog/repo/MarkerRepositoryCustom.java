@@ -28,6 +28,8 @@ public interface MarkerRepositoryCustom {
             double x,
             double y,
             String title,
-            String description
+            String description,
+            Integer minZoom,
+            Integer maxZoom
     ) {}
 }
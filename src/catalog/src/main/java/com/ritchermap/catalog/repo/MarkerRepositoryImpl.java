@@ -24,8 +24,8 @@ public class MarkerRepositoryImpl implements MarkerRepositoryCustom {
     }
 
     private static final String INSERT_SQL =
-            "INSERT INTO markers (map_id, category_id, title, description, geom) " +
-                    "VALUES (?, ?, ?, ?, ST_SetSRID(ST_MakePoint(?, ?), 0))";
+            "INSERT INTO markers (map_id, category_id, title, description, geom, min_zoom, max_zoom) " +
+                    "VALUES (?, ?, ?, ?, ST_SetSRID(ST_MakePoint(?, ?), 0), ?, ?)";
 
     @Override
     public int bulkInsert(List<MarkerInsert> rows) {
@@ -39,6 +39,8 @@ public class MarkerRepositoryImpl implements MarkerRepositoryCustom {
                 if (r.description() == null) ps.setNull(4, Types.VARCHAR); else ps.setString(4, r.description());
                 ps.setDouble(5, r.x());
                 ps.setDouble(6, r.y());
+                if (r.minZoom() == null) ps.setNull(7, Types.INTEGER); else ps.setInt(7, r.minZoom());
+                if (r.maxZoom() == null) ps.setNull(8, Types.INTEGER); else ps.setInt(8, r.maxZoom());
             }
             @Override public int getBatchSize() { return rows.size(); }
         });
@@ -50,6 +50,14 @@ public class MarkerService {
         return p;
     }
 
+    /** Both ends are optional; when both are set the range must not be empty. */
+    private static void requireZoomRange(Integer minZoom, Integer maxZoom) {
+        if (minZoom != null && maxZoom != null && minZoom > maxZoom) {
+            throw new IllegalArgumentException(
+                    "marker minZoom " + minZoom + " is above maxZoom " + maxZoom);
+        }
+    }
+
     private void requireMapAndCategory(long mapId, long categoryId) {
         if (!maps.existsById(mapId)) throw NotFoundException.of("map", mapId);
         var cat = categories.findById(categoryId)
@@ -67,9 +75,11 @@ public class MarkerService {
 
     @Transactional
     public Marker create(long mapId, long categoryId, double x, double y,
-                         String title, String description) {
+                         String title, String description, Integer minZoom, Integer maxZoom) {
         requireMapAndCategory(mapId, categoryId);
-        Marker m = new Marker(mapId, categoryId, point(x, y), title, description);
+        requireZoomRange(minZoom, maxZoom);
+        Marker m = new Marker(mapId, categoryId, point(x, y), title, description,
+                minZoom, maxZoom);
         Marker saved = markers.save(m);
         events.publishEvent(markerChanged(mapId, CatalogChanged.Action.ACTION_CREATED));
         return saved;
@@ -77,10 +87,11 @@ public class MarkerService {
 
     @Transactional
     public Marker update(long id, long categoryId, double x, double y,
-                         String title, String description) {
+                         String title, String description, Integer minZoom, Integer maxZoom) {
         Marker m = markers.findById(id).orElseThrow(() -> NotFoundException.of("marker", id));
         requireMapAndCategory(m.getMapId(), categoryId);
-        m.update(categoryId, point(x, y), title, description);
+        requireZoomRange(minZoom, maxZoom);
+        m.update(categoryId, point(x, y), title, description, minZoom, maxZoom);
         events.publishEvent(markerChanged(m.getMapId(), CatalogChanged.Action.ACTION_UPDATED));
         return m;
     }
@@ -110,6 +121,7 @@ public class MarkerService {
                 throw new IllegalArgumentException(
                         "marker mapId " + r.mapId() + " does not match path mapId " + mapId);
             }
+            requireZoomRange(r.minZoom(), r.maxZoom());
         }
         int n = markers.bulkInsert(rows);
         events.publishEvent(markerChanged(mapId, CatalogChanged.Action.ACTION_BULK_IMPORTED));
@@ -0,0 +1,6 @@
+-- Per-marker visible zoom range (inclusive). A tiny item shouldn't show at
+-- world zoom: the Rust read path only returns markers whose range includes the
+-- requested zoom. NULL = unbounded on that side, so existing rows stay visible
+-- at every zoom.
+ALTER TABLE markers ADD COLUMN min_zoom INT;
+ALTER TABLE markers ADD COLUMN max_zoom INT;
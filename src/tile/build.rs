@@ -12,10 +12,18 @@
 //! to `/proto` and pointed at via `RITCHERMAP_PROTO_DIR`. We compile ONLY
 //! `ritchermap/catalog/v1/catalog.proto` into `OUT_DIR`; `src/events.rs`
 //! `include!`s the result as `catalog_v1`.
+//!
+//! It also stamps build provenance for `GET /version`: `TILE_GIT_SHA` (from
+//! `GIT_SHA`, else `git rev-parse`, else `unknown`) and `TILE_BUILD_TIMESTAMP`
+//! (unix seconds; `SOURCE_DATE_EPOCH` wins for reproducible builds).
 
 use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 fn main() {
+    build_info();
+
     let proto_dir =
         std::env::var("RITCHERMAP_PROTO_DIR").unwrap_or_else(|_| "../../proto".to_string());
     let proto_dir = PathBuf::from(proto_dir);
@@ -36,3 +44,65 @@ fn main() {
         .compile_fds(file_descriptors)
         .expect("prost-build failed to generate Rust from the catalog descriptor set");
 }
+
+/// Emit `TILE_GIT_SHA` / `TILE_BUILD_TIMESTAMP` for `env!` in the crate.
+///
+/// The Docker build context has no `.git`, so images pass `GIT_SHA` as a
+/// build arg; local builds fall back to asking git, and rerun when `HEAD` (or
+/// the branch it points at) moves so a dev build never reports an old SHA.
+fn build_info() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    for path in git_head_files() {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+
+    let sha = std::env::var("GIT_SHA")
+        .ok()
+        .filter(|s| !s.trim().is_empty())
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "--short=12", "HEAD"])
+                .output()
+                .ok()
+                .filter(|o| o.status.success())
+                .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=TILE_GIT_SHA={sha}");
+
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        });
+    println!("cargo:rustc-env=TILE_BUILD_TIMESTAMP={timestamp}");
+}
+
+/// `.git/HEAD` and the ref file it names, where they exist. A missing path
+/// would make cargo rerun on every build, so outside a checkout (and for a
+/// packed or detached ref) this yields less rather than guessing.
+fn git_head_files() -> Vec<PathBuf> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+    };
+    let mut files = Vec::new();
+    files.extend(git(&["rev-parse", "--git-path", "HEAD"]));
+    if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+        files.extend(git(&["rev-parse", "--git-path", &branch]));
+    }
+    files
+        .into_iter()
+        .map(PathBuf::from)
+        .filter(|p| p.exists())
+        .collect()
+}
@@ -0,0 +1,463 @@
+//! Operator endpoints under `/admin`, mounted only when `ADMIN_TOKEN` is set.
+//!
+//! Every route demands `Authorization: Bearer <ADMIN_TOKEN>`. Destructive
+//! routes additionally require a confirmation phrase in the JSON body, so a
+//! stray curl (or a replayed GET-turned-POST) can't flush production.
+//!
+//!   * `POST /admin/cache/purge-all` — `{"confirm": "purge-all",
+//!     "memory_only": true}`; `memory_only` keeps the disk tier
+//!   * `POST /admin/cache/invalidate` — `{"tile": "<prefix>/<z>/<x>/<y>.<ext>",
+//!     "ancestors": true}`; `ancestors` also drops every lower-zoom tile
+//!     covering it
+//!   * `POST /admin/cache/invalidate-region` — `{"map_id": 1, "bbox":
+//!     "minx,miny,maxx,maxy", "dry_run": true}`; every tile over that pixel
+//!     box at every zoom, refused above [`region::MAX_REGION_TILES`];
+//!     `dry_run` only counts them
+//!   * `POST /admin/cache/invalidate-point` — `{"map_id": 1, "x": 10, "y": 20}`;
+//!     the tile under that pixel at every zoom
+//!   * `GET /admin/cache/stats/{map_id}` — what the tile cache holds for one
+//!     map (tiles, cached misses, bytes)
+//!   * `GET /admin/config` — the effective [`ServeConfig`] and
+//!     [`WiringConfig`] after env parsing, secrets redacted
+//!
+//! Bodies over `ADMIN_BODY_LIMIT_BYTES` are refused with 413.
+
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, State},
+    http::{header, HeaderMap},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ServeConfig, WiringConfig};
+use crate::http::{parse_tile_path, ApiError, SharedState};
+use crate::repo::MarkerRepo;
+use crate::tiles::{PrefixStats, TileOrigin};
+
+pub mod region;
+
+/// Body value `POST /admin/cache/purge-all` must carry in `confirm`.
+pub const PURGE_ALL_CONFIRMATION: &str = "purge-all";
+
+pub fn routes<R: MarkerRepo, O: TileOrigin>(body_limit: usize) -> Router<SharedState<R, O>> {
+    Router::new()
+        .route("/admin/cache/purge-all", post(purge_all_handler::<R, O>))
+        .route("/admin/cache/invalidate", post(invalidate_handler::<R, O>))
+        .route(
+            "/admin/cache/invalidate-region",
+            post(region::invalidate_region_handler::<R, O>),
+        )
+        .route(
+            "/admin/cache/invalidate-point",
+            post(region::invalidate_point_handler::<R, O>),
+        )
+        .route("/admin/cache/stats/{map_id}", get(stats_handler::<R, O>))
+        .route("/admin/config", get(config_handler::<R, O>))
+        .layer(DefaultBodyLimit::max(body_limit))
+}
+
+#[derive(Debug, Deserialize)]
+struct PurgeAllRequest {
+    #[serde(default)]
+    confirm: String,
+    #[serde(default)]
+    memory_only: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PurgeAllResponse {
+    /// Cached tile entries (including cached misses) that were dropped.
+    pub removed: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct InvalidateRequest {
+    /// Tile path as served under `/tiles/` (either address shape).
+    tile: String,
+    #[serde(default)]
+    ancestors: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvalidateResponse {
+    /// Cache keys dropped: 1, or `z + 1` with `ancestors`.
+    pub invalidated: u32,
+}
+
+/// Both configs as one flat object (their keys don't overlap).
+#[derive(Debug, Serialize)]
+pub struct ConfigResponse {
+    #[serde(flatten)]
+    pub serve: ServeConfig,
+    #[serde(flatten)]
+    pub wiring: WiringConfig,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheStatsResponse {
+    pub map_id: i64,
+    pub prefix: String,
+    #[serde(flatten)]
+    pub stats: PrefixStats,
+}
+
+/// Check the bearer token. Compared in constant time: the token is the only
+/// thing standing between the network and a cache flush.
+fn authorize(headers: &HeaderMap, cfg: &ServeConfig) -> Result<(), ApiError> {
+    let expected = cfg.admin_token.as_deref().ok_or(ApiError::Unauthorized)?;
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or(ApiError::Unauthorized)?;
+    let (a, b) = (expected.as_bytes(), given.as_bytes());
+    let diff = a
+        .iter()
+        .zip(b)
+        .fold(a.len() ^ b.len(), |acc, (x, y)| acc | usize::from(x ^ y));
+    if diff == 0 {
+        Ok(())
+    } else {
+        Err(ApiError::Unauthorized)
+    }
+}
+
+async fn purge_all_handler<R: MarkerRepo, O: TileOrigin>(
+    State(state): State<SharedState<R, O>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<PurgeAllResponse>, ApiError> {
+    authorize(&headers, &state.config)?;
+    let req: PurgeAllRequest = parse_body(&body)?;
+    if req.confirm != PURGE_ALL_CONFIRMATION {
+        return Err(ApiError::BadRequest(format!(
+            "set \"confirm\": {PURGE_ALL_CONFIRMATION:?} to purge every cached tile"
+        )));
+    }
+
+    let removed = state.tiles.purge_all(req.memory_only).await;
+    tracing::warn!(
+        removed,
+        memory_only = req.memory_only,
+        "tile cache purged (admin purge-all)"
+    );
+    Ok(Json(PurgeAllResponse { removed }))
+}
+
+async fn invalidate_handler<R: MarkerRepo, O: TileOrigin>(
+    State(state): State<SharedState<R, O>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<InvalidateResponse>, ApiError> {
+    authorize(&headers, &state.config)?;
+    let req: InvalidateRequest = parse_body(&body)?;
+    let id = parse_tile_path(&req.tile)?;
+
+    let invalidated = state.tiles.invalidate_tile(&id, req.ancestors).await;
+    tracing::info!(tile = %id.key(), ancestors = req.ancestors, "tile invalidated (admin)");
+    Ok(Json(InvalidateResponse { invalidated }))
+}
+
+async fn stats_handler<R: MarkerRepo, O: TileOrigin>(
+    State(state): State<SharedState<R, O>>,
+    headers: HeaderMap,
+    Path(map_id): Path<i64>,
+) -> Result<Json<CacheStatsResponse>, ApiError> {
+    authorize(&headers, &state.config)?;
+    let prefix = state
+        .repo
+        .prefix_for_map(map_id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    let stats = state.tiles.prefix_stats(&prefix).await;
+    Ok(Json(CacheStatsResponse {
+        map_id,
+        prefix,
+        stats,
+    }))
+}
+
+async fn config_handler<R: MarkerRepo, O: TileOrigin>(
+    State(state): State<SharedState<R, O>>,
+    headers: HeaderMap,
+) -> Result<Json<ConfigResponse>, ApiError> {
+    authorize(&headers, &state.config)?;
+    Ok(Json(ConfigResponse {
+        serve: state.config.clone(),
+        wiring: state.wiring.clone(),
+    }))
+}
+
+/// Admin bodies are small JSON objects; any parse failure is the caller's.
+fn parse_body<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+    serde_json::from_slice(body).map_err(|e| ApiError::BadRequest(format!("bad JSON body: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    use crate::http::router;
+    use crate::http::tests::{state_with, StaticOrigin};
+    use crate::repo::InMemoryRepo;
+    use crate::tiles::{LocalTileOrigin, TieredTileOrigin, TileError, TileId};
+
+    /// Admin config guarded by `admin_token` (or disabled without one).
+    fn admin(admin_token: Option<&str>) -> ServeConfig {
+        ServeConfig {
+            admin_token: admin_token.map(Into::into),
+            ..Default::default()
+        }
+    }
+
+    fn state(admin_token: Option<&str>) -> SharedState<InMemoryRepo, StaticOrigin> {
+        state_with(StaticOrigin, admin(admin_token))
+    }
+
+    /// Warm the cache with tiles under two prefixes.
+    async fn warm(state: &SharedState<InMemoryRepo, StaticOrigin>) {
+        for prefix in ["a", "b/c"] {
+            for x in 0..3 {
+                let id = TileId {
+                    prefix: prefix.into(),
+                    z: 2,
+                    x,
+                    y: 0,
+                    ext: "webp".into(),
+                };
+                state.tiles.get(id).await.unwrap();
+            }
+        }
+        state.tiles.run_pending_for_test().await;
+        assert_eq!(state.tiles.entry_count_for_test(), 6);
+    }
+
+    fn purge(token: Option<&str>, body: &str) -> Request<Body> {
+        let mut req = Request::post("/admin/cache/purge-all");
+        if let Some(t) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {t}"));
+        }
+        req.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn purge_is_rejected_without_auth_or_confirmation() {
+        let state = state(Some("s3cret"));
+        warm(&state).await;
+        let ok_body = r#"{"confirm":"purge-all"}"#;
+
+        let cases = [
+            (purge(None, ok_body), StatusCode::UNAUTHORIZED),
+            (purge(Some("wrong"), ok_body), StatusCode::UNAUTHORIZED),
+            (purge(Some("s3cret"), "{}"), StatusCode::BAD_REQUEST),
+            (
+                purge(Some("s3cret"), r#"{"confirm":"yes"}"#),
+                StatusCode::BAD_REQUEST,
+            ),
+        ];
+        for (req, want) in cases {
+            let resp = router(state.clone()).oneshot(req).await.unwrap();
+            assert_eq!(resp.status(), want);
+        }
+        state.tiles.run_pending_for_test().await;
+        assert_eq!(state.tiles.entry_count_for_test(), 6);
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_are_refused_with_413() {
+        let state = state(Some("s3cret"));
+        warm(&state).await;
+        // Valid JSON, just padded past the limit.
+        let padded = format!(
+            r#"{{"confirm":"purge-all"{}}}"#,
+            " ".repeat(crate::config::DEFAULT_ADMIN_BODY_LIMIT)
+        );
+
+        let resp = router(state.clone())
+            .oneshot(purge(Some("s3cret"), &padded))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        state.tiles.run_pending_for_test().await;
+        assert_eq!(state.tiles.entry_count_for_test(), 6);
+    }
+
+    #[tokio::test]
+    async fn confirmed_purge_clears_every_prefix() {
+        let state = state(Some("s3cret"));
+        warm(&state).await;
+
+        let resp = router(state.clone())
+            .oneshot(purge(Some("s3cret"), r#"{"confirm":"purge-all"}"#))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(v["removed"], 6);
+        assert_eq!(state.tiles.entry_count_for_test(), 0);
+    }
+
+    #[tokio::test]
+    async fn memory_only_purge_keeps_the_disk_tier() {
+        let dir = std::env::temp_dir().join(format!("admin-purge-test-{}", std::process::id()));
+        let state = state_with(
+            TieredTileOrigin::new(&dir, StaticOrigin),
+            admin(Some("s3cret")),
+        );
+        let id = parse_tile_path("m/2/1/0.webp").unwrap();
+        state.tiles.get(id.clone()).await.unwrap();
+        let disk = LocalTileOrigin::new(&dir);
+        assert!(disk.exists(&id).await.unwrap());
+
+        let body = r#"{"confirm":"purge-all","memory_only":true}"#;
+        let resp = router(state.clone())
+            .oneshot(purge(Some("s3cret"), body))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(state.tiles.entry_count_for_test(), 0);
+        assert!(disk.exists(&id).await.unwrap());
+
+        // A full purge empties the disk tier too.
+        state.tiles.get(id.clone()).await.unwrap();
+        let resp = router(state.clone())
+            .oneshot(purge(Some("s3cret"), r#"{"confirm":"purge-all"}"#))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(!disk.exists(&id).await.unwrap());
+
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn admin_routes_are_unmounted_without_a_token() {
+        let resp = router(state(None))
+            .oneshot(purge(Some(""), r#"{"confirm":"purge-all"}"#))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn invalidate_reports_the_ancestor_walk() {
+        let state = state(Some("s3cret"));
+        let call = |body: &'static str| {
+            let state = state.clone();
+            async move {
+                let mut req = purge(Some("s3cret"), body);
+                *req.uri_mut() = "/admin/cache/invalidate".parse().unwrap();
+                router(state).oneshot(req).await.unwrap()
+            }
+        };
+
+        let resp = call(r#"{"tile":"a/5/3/1.webp","ancestors":true}"#).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(v["invalidated"], 6);
+
+        let resp = call(r#"{"tile":"a/5/3/1.jpg"}"#).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn stats_report_only_the_maps_own_entries() {
+        /// Tiles exist only in column 0; their size is `y + 1` bytes.
+        struct Sparse;
+        #[async_trait::async_trait]
+        impl TileOrigin for Sparse {
+            async fn get(&self, id: &TileId) -> Result<Bytes, TileError> {
+                match id.x {
+                    0 => Ok(Bytes::from(vec![0u8; id.y as usize + 1])),
+                    _ => Err(TileError::NotFound),
+                }
+            }
+        }
+        let state = state_with(Sparse, admin(Some("s3cret")));
+        // Map 1 is prefix "m": two tiles (1 + 2 bytes) and one miss.
+        for (prefix, x, y) in [("m", 0, 0), ("m", 0, 1), ("m", 1, 0), ("other", 0, 9)] {
+            let id = TileId {
+                prefix: prefix.into(),
+                z: 1,
+                x,
+                y,
+                ext: "webp".into(),
+            };
+            let _ = state.tiles.get(id).await;
+        }
+
+        let get = |path: &str| {
+            Request::get(path)
+                .header(header::AUTHORIZATION, "Bearer s3cret")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let resp = router(state.clone())
+            .oneshot(get("/admin/cache/stats/1"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            v,
+            serde_json::json!({"map_id": 1, "prefix": "m", "tiles": 2, "misses": 1, "bytes": 3})
+        );
+
+        let resp = router(state)
+            .oneshot(get("/admin/cache/stats/7"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn config_dump_redacts_the_admin_token() {
+        let mut state = state(Some("s3cret"));
+        Arc::get_mut(&mut state).unwrap().wiring.database_url =
+            "postgres://app:hunter2@db:5432/maps".into();
+        let req = |token: &str| {
+            Request::get("/admin/config")
+                .header(header::AUTHORIZATION, format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let resp = router(state.clone()).oneshot(req("wrong")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let resp = router(state).oneshot(req("s3cret")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8_lossy(&body);
+        assert!(
+            !text.contains("s3cret") && !text.contains("hunter2"),
+            "{text}"
+        );
+        let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(v["admin_token"], crate::config::REDACTED);
+        assert_eq!(v["tile_miss_max_age"], 300);
+        assert_eq!(v["slow_tile_fetch_ms"], 500);
+        assert_eq!(v["request_timeout_ms"], serde_json::Value::Null);
+        assert_eq!(v["database_url"], "postgres://app:<redacted>@db:5432/maps");
+        assert_eq!(v["tile_digest"], "sha-256");
+        assert_eq!(v["startup_selftest"], "off");
+        assert_eq!(v["kafka_brokers"], serde_json::Value::Null);
+    }
+}
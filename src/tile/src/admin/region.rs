@@ -0,0 +1,300 @@
+//! Area invalidation: drop every cached tile over part of a map, at every
+//! zoom, after an edit that only touched that area.
+
+use axum::{body::Bytes, extract::State, http::HeaderMap, Json};
+use serde::{Deserialize, Serialize};
+
+use super::{authorize, parse_body};
+use crate::domain::BBox;
+use crate::grid::{TileCoord, TileGrid};
+use crate::http::{parse_bbox, ApiError, SharedState};
+use crate::repo::MarkerRepo;
+use crate::tiles::{TileId, TileOrigin};
+
+/// Most tiles one region invalidation may cover, over all zooms. Counted
+/// up front, arithmetically, so an oversized box is refused before any work.
+pub const MAX_REGION_TILES: u64 = 100_000;
+
+/// Every format the tiler writes; a tile is dropped under each.
+const TILE_FORMATS: [&str; 2] = ["webp", "png"];
+
+#[derive(Debug, Deserialize)]
+struct RegionRequest {
+    map_id: i64,
+    /// `minx,miny,maxx,maxy` in native pixels, as for viewport queries.
+    bbox: String,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegionResponse {
+    /// Tiles the region covers over every zoom (each dropped in every format).
+    pub tiles: u64,
+    /// Nothing was dropped; `tiles` is only the count.
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct PointRequest {
+    map_id: i64,
+    /// Native pixel position.
+    x: f64,
+    y: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PointResponse {
+    /// `z/x/y` of the dropped tiles, lowest zoom first; empty off the map.
+    pub tiles: Vec<String>,
+}
+
+pub(super) async fn invalidate_region_handler<R: MarkerRepo, O: TileOrigin>(
+    State(state): State<SharedState<R, O>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<RegionResponse>, ApiError> {
+    authorize(&headers, &state.config)?;
+    let req: RegionRequest = parse_body(&body)?;
+    let bbox = parse_bbox(&req.bbox)?;
+    let (prefix, grid) = map_grid(&state, req.map_id).await?;
+
+    let tiles: u64 = (0..=top_zoom(&grid))
+        .map(|z| grid.count_in_bounds(&bbox, z))
+        .sum();
+    if tiles > MAX_REGION_TILES {
+        return Err(ApiError::BadRequest(format!(
+            "region covers {tiles} tiles; the limit is {MAX_REGION_TILES}"
+        )));
+    }
+    if !req.dry_run {
+        for c in tiles_in_region(&grid, &bbox) {
+            drop_tile(&state, &prefix, c).await;
+        }
+        tracing::info!(map_id = req.map_id, %prefix, tiles, ?bbox, "region invalidated (admin)");
+    }
+    Ok(Json(RegionResponse {
+        tiles,
+        dry_run: req.dry_run,
+    }))
+}
+
+pub(super) async fn invalidate_point_handler<R: MarkerRepo, O: TileOrigin>(
+    State(state): State<SharedState<R, O>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<PointResponse>, ApiError> {
+    authorize(&headers, &state.config)?;
+    let req: PointRequest = parse_body(&body)?;
+    let (prefix, grid) = map_grid(&state, req.map_id).await?;
+
+    let covering = grid.covering_tiles(req.x, req.y, 0, top_zoom(&grid));
+    for &c in &covering {
+        drop_tile(&state, &prefix, c).await;
+    }
+    tracing::info!(map_id = req.map_id, %prefix, x = req.x, y = req.y, "point invalidated (admin)");
+    Ok(Json(PointResponse {
+        tiles: covering
+            .iter()
+            .map(|c| format!("{}/{}/{}", c.z, c.x, c.y))
+            .collect(),
+    }))
+}
+
+/// The map's key prefix and tile grid; 404 for an unknown map.
+async fn map_grid<R: MarkerRepo, O: TileOrigin>(
+    state: &SharedState<R, O>,
+    map_id: i64,
+) -> Result<(String, TileGrid), ApiError> {
+    let meta = state
+        .repo
+        .map_meta(map_id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    let prefix = state
+        .repo
+        .prefix_for_map(map_id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Ok((prefix, TileGrid::new(&meta)))
+}
+
+/// Highest zoom the pyramid has (0 for a malformed negative `max_zoom`,
+/// whose grid is empty anyway).
+fn top_zoom(grid: &TileGrid) -> u32 {
+    u32::try_from(grid.max_zoom).unwrap_or(0)
+}
+
+/// Every tile overlapping `bbox`, found by walking the quadtree down from
+/// 0/0/0: a tile that misses the box (or the grid) has no children that
+/// hit it. Overlap is the same as [`TileGrid::count_in_bounds`].
+fn tiles_in_region(grid: &TileGrid, bbox: &BBox) -> Vec<TileCoord> {
+    let mut found = Vec::new();
+    let mut stack = vec![TileCoord::new(0, 0, 0)];
+    while let Some(c) = stack.pop() {
+        let Some(b) = grid.tile_bounds(c) else {
+            continue;
+        };
+        let overlaps = b.min_x <= bbox.max_x
+            && b.max_x > bbox.min_x
+            && b.min_y <= bbox.max_y
+            && b.max_y > bbox.min_y;
+        if !overlaps {
+            continue;
+        }
+        found.push(c);
+        if c.z < top_zoom(grid) {
+            stack.extend(c.children());
+        }
+    }
+    found
+}
+
+async fn drop_tile<R: MarkerRepo, O: TileOrigin>(
+    state: &SharedState<R, O>,
+    prefix: &str,
+    c: TileCoord,
+) {
+    for ext in TILE_FORMATS {
+        let id = TileId {
+            prefix: prefix.to_string(),
+            z: c.z,
+            x: c.x,
+            y: c.y,
+            ext: ext.to_string(),
+        };
+        state.tiles.invalidate_tile(&id, false).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use tower::ServiceExt;
+
+    use crate::config::ServeConfig;
+    use crate::http::router;
+    use crate::http::tests::{app_state, StaticOrigin};
+    use crate::repo::{InMemoryRepo, MapMeta};
+    use crate::tiles::CachedTiles;
+
+    /// Map 1 ("m"): 100x100 px in 16 px tiles up to zoom 4, so zooms 0..=4
+    /// have 1, 1, 2x2, 4x4 and 7x7 tiles.
+    fn state(width: i64) -> SharedState<InMemoryRepo, StaticOrigin> {
+        let repo = InMemoryRepo {
+            markers: Vec::new(),
+            markers_map_id: 1,
+            meta: MapMeta {
+                width,
+                height: 100,
+                max_zoom: 4,
+                tile_size: 16,
+            },
+            prefix: "m".into(),
+        };
+        let config = ServeConfig {
+            admin_token: Some("s3cret".into()),
+            ..Default::default()
+        };
+        app_state(repo, CachedTiles::new(StaticOrigin, 1024 * 1024), config)
+    }
+
+    /// Cache every webp tile of the pyramid; returns how many.
+    async fn warm(state: &SharedState<InMemoryRepo, StaticOrigin>) -> u64 {
+        let grid = TileGrid::new(&state.repo.meta);
+        let mut n = 0;
+        for z in 0..=4 {
+            let (cols, rows) = grid.dimensions(z);
+            for (x, y) in (0..cols as u32).flat_map(|x| (0..rows as u32).map(move |y| (x, y))) {
+                let id = crate::http::parse_tile_path(&format!("m/{z}/{x}/{y}.webp")).unwrap();
+                state.tiles.get(id).await.unwrap();
+                n += 1;
+            }
+        }
+        state.tiles.run_pending_for_test().await;
+        n
+    }
+
+    async fn post(
+        state: &SharedState<InMemoryRepo, StaticOrigin>,
+        path: &str,
+        body: &str,
+    ) -> (StatusCode, serde_json::Value) {
+        let req = Request::post(path)
+            .header(header::AUTHORIZATION, "Bearer s3cret")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let resp = router(state.clone()).oneshot(req).await.unwrap();
+        let status = resp.status();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        state.tiles.run_pending_for_test().await;
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn region_drops_the_covered_tiles_at_every_zoom() {
+        let state = state(100);
+        let cached = warm(&state).await;
+        assert_eq!(cached, 1 + 1 + 4 + 16 + 49);
+
+        // 0..20 px spans two 16 px columns/rows at zoom 4, one tile above.
+        let body = r#"{"map_id": 1, "bbox": "0,0,20,20", "dry_run": true}"#;
+        let (status, v) = post(&state, "/admin/cache/invalidate-region", body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(v["tiles"], 4 + 1 + 1 + 1 + 1);
+        assert_eq!(state.tiles.entry_count_for_test(), cached);
+
+        let body = r#"{"map_id": 1, "bbox": "0,0,20,20"}"#;
+        let (_, v) = post(&state, "/admin/cache/invalidate-region", body).await;
+        assert_eq!(v["tiles"], 8);
+        assert_eq!(state.tiles.entry_count_for_test(), cached - 8);
+
+        // The walk and the arithmetic count agree on odd boxes too.
+        let grid = TileGrid::new(&state.repo.meta);
+        for bbox in [
+            BBox::new(15.0, 31.0, 99.0, 64.0),
+            BBox::new(-5.0, 90.0, 500.0, 500.0),
+        ] {
+            let counted: u64 = (0..=4).map(|z| grid.count_in_bounds(&bbox, z)).sum();
+            assert_eq!(
+                tiles_in_region(&grid, &bbox).len() as u64,
+                counted,
+                "{bbox:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_regions_and_unknown_maps_are_refused() {
+        let state = state(1_000_000);
+        let body = r#"{"map_id": 1, "bbox": "0,0,1000000,100"}"#;
+        let (status, _) = post(&state, "/admin/cache/invalidate-region", body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let body = r#"{"map_id": 2, "bbox": "0,0,10,10"}"#;
+        let (status, _) = post(&state, "/admin/cache/invalidate-region", body).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn point_drops_the_tile_under_it_at_every_zoom() {
+        let state = state(100);
+        let cached = warm(&state).await;
+
+        let body = r#"{"map_id": 1, "x": 50, "y": 20}"#;
+        let (status, v) = post(&state, "/admin/cache/invalidate-point", body).await;
+        assert_eq!(status, StatusCode::OK);
+        let want = ["0/0/0", "1/0/0", "2/0/0", "3/1/0", "4/3/1"];
+        assert_eq!(v["tiles"], serde_json::json!(want));
+        assert_eq!(state.tiles.entry_count_for_test(), cached - 5);
+
+        let body = r#"{"map_id": 1, "x": 500, "y": 20}"#;
+        let (_, v) = post(&state, "/admin/cache/invalidate-point", body).await;
+        assert_eq!(v["tiles"], serde_json::json!([]));
+    }
+}
@@ -101,6 +101,8 @@ mod tests {
             x,
             y,
             title: None,
+            min_zoom: None,
+            max_zoom: None,
         }
     }
 
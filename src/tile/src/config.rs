@@ -0,0 +1,388 @@
+//! Settings read from the environment at startup.
+//!
+//! Every field defaults to the service's historical behaviour, so leaving a
+//! variable unset never changes what a deployment does. [`ServeConfig`] is
+//! what the handlers consult per request; [`WiringConfig`] is what `main.rs`
+//! builds the service from (DATABASE_URL, TILE_ORIGIN, BIND_ADDR, ...). Both
+//! are carried in `AppState`, so `/admin/config` can show them.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::grid::{TileCoord, TileRange, MAX_QUADKEY_LEN};
+use crate::tiles::TileId;
+
+mod wiring;
+
+pub use wiring::{mask_password, WiringConfig, DEFAULT_BIND_ADDR, DEFAULT_TILE_CACHE_MB};
+
+/// `Cache-Control` for tiles when no per-zoom policy is configured: tiles are
+/// immutable per key, so the CDN and browser may hold them forever.
+pub const IMMUTABLE_TILE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("{var}: {reason}")]
+    Invalid { var: &'static str, reason: String },
+    #[error("{0} is required")]
+    Missing(&'static str),
+}
+
+/// Default `max-age` (seconds) on tile 404s; see [`ServeConfig::tile_miss_max_age`].
+pub const DEFAULT_TILE_MISS_MAX_AGE: u32 = 300;
+
+/// The publicly servable part of one map's pyramid. Tiles outside it 404
+/// whether or not they exist.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PublishedWindow {
+    pub min_zoom: u32,
+    pub max_zoom: u32,
+    /// Region as a tile rectangle at one zoom; absent = the whole map.
+    #[serde(default)]
+    pub region: Option<TileRange>,
+}
+
+impl PublishedWindow {
+    pub fn allows(&self, c: TileCoord) -> bool {
+        (self.min_zoom..=self.max_zoom).contains(&c.z) && self.region.is_none_or(|r| r.overlaps(c))
+    }
+}
+
+/// What `/tiles/*` answers for a tile the origin doesn't have
+/// (`TILE_ON_MISSING`). Tiles are pre-rendered by the tiler, so there is no
+/// generate-on-miss mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnMissing {
+    /// 404, which MapLibre treats as a transparent tile.
+    #[default]
+    NotFound,
+    /// 200 with a transparent placeholder in the requested format, for
+    /// clients that paint 404s as error tiles.
+    Blank,
+}
+
+impl FromStr for OnMissing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "not_found" => Ok(Self::NotFound),
+            "blank" => Ok(Self::Blank),
+            "generate" => Err("tiles are pre-rendered by the tiler; use not_found or blank".into()),
+            other => Err(format!("expected not_found or blank, got {other:?}")),
+        }
+    }
+}
+
+/// Default cap (bytes) on admin request bodies; see [`ServeConfig::admin_body_limit`].
+pub const DEFAULT_ADMIN_BODY_LIMIT: usize = 16 * 1024;
+
+/// Default slow-operation thresholds, in milliseconds.
+pub const DEFAULT_SLOW_MARKER_QUERY_MS: u64 = 250;
+pub const DEFAULT_SLOW_TILE_FETCH_MS: u64 = 500;
+
+/// Per-request knobs for the HTTP layer.
+///
+/// Serializes as the effective configuration for `GET /admin/config`:
+/// secrets are redacted and durations are in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServeConfig {
+    /// Bearer token guarding `/admin/*` (`ADMIN_TOKEN`). `None` leaves the
+    /// admin routes unmounted, so they 404 like any unknown path.
+    #[serde(serialize_with = "redacted")]
+    pub admin_token: Option<String>,
+    /// Largest accepted `/admin/*` request body (`ADMIN_BODY_LIMIT_BYTES`);
+    /// bigger ones get a 413 before being buffered. The admin bodies are a
+    /// few dozen bytes of JSON, so the default is small.
+    pub admin_body_limit: usize,
+    /// Tile `max-age` by zoom (`TILE_MAX_AGE_BY_ZOOM`): each key is the lowest
+    /// zoom its value applies to, up to the next key. Empty = immutable.
+    pub tile_max_age: BTreeMap<u32, u32>,
+    /// `max-age` on tile 404s (`TILE_MISS_MAX_AGE`), and the lifetime of a
+    /// miss in the tile cache. Short, because a miss turns into a tile the
+    /// moment the map is re-tiled.
+    pub tile_miss_max_age: u32,
+    /// Response for tiles the origin doesn't have (`TILE_ON_MISSING`).
+    pub on_missing: OnMissing,
+    /// `Content-Type` overrides by tile format (`TILE_CONTENT_TYPES`, e.g.
+    /// `webp=image/png`), an interop escape hatch for legacy clients/CDNs.
+    pub tile_content_types: BTreeMap<String, String>,
+    /// Viewport marker queries slower than this are logged and counted
+    /// (`SLOW_MARKER_QUERY_MS`).
+    #[serde(rename = "slow_marker_query_ms", serialize_with = "millis")]
+    pub slow_marker_query: Duration,
+    /// Tile reads (cache + origin) slower than this are logged and counted
+    /// (`SLOW_TILE_FETCH_MS`).
+    #[serde(rename = "slow_tile_fetch_ms", serialize_with = "millis")]
+    pub slow_tile_fetch: Duration,
+    /// Per-request deadline (`REQUEST_TIMEOUT_SECS`); overruns get a 408.
+    /// `None` (unset or 0) = no deadline.
+    #[serde(rename = "request_timeout_ms", serialize_with = "opt_millis")]
+    pub request_timeout: Option<Duration>,
+    /// Requests handled at once (`MAX_CONNECTIONS`); more wait for a slot.
+    /// Probes are exempt. `None` (unset or 0) = unlimited.
+    pub max_connections: Option<usize>,
+    /// Published windows by map prefix (`TILE_PUBLISHED_WINDOWS`, JSON object
+    /// of prefix -> window). Prefixes not listed are served in full.
+    pub published: BTreeMap<String, PublishedWindow>,
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        Self {
+            admin_token: None,
+            admin_body_limit: DEFAULT_ADMIN_BODY_LIMIT,
+            tile_max_age: BTreeMap::new(),
+            tile_miss_max_age: DEFAULT_TILE_MISS_MAX_AGE,
+            on_missing: OnMissing::default(),
+            tile_content_types: BTreeMap::new(),
+            slow_marker_query: Duration::from_millis(DEFAULT_SLOW_MARKER_QUERY_MS),
+            slow_tile_fetch: Duration::from_millis(DEFAULT_SLOW_TILE_FETCH_MS),
+            request_timeout: None,
+            max_connections: None,
+            published: BTreeMap::new(),
+        }
+    }
+}
+
+impl ServeConfig {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Ok(Self {
+            admin_token: std::env::var("ADMIN_TOKEN")
+                .ok()
+                .filter(|t| !t.trim().is_empty()),
+            admin_body_limit: env_number("ADMIN_BODY_LIMIT_BYTES", DEFAULT_ADMIN_BODY_LIMIT)?,
+            tile_max_age: match std::env::var("TILE_MAX_AGE_BY_ZOOM") {
+                Ok(v) => parse_zoom_map(&v).map_err(|reason| ConfigError::Invalid {
+                    var: "TILE_MAX_AGE_BY_ZOOM",
+                    reason,
+                })?,
+                Err(_) => BTreeMap::new(),
+            },
+            tile_miss_max_age: env_number("TILE_MISS_MAX_AGE", DEFAULT_TILE_MISS_MAX_AGE)?,
+            on_missing: match std::env::var("TILE_ON_MISSING") {
+                Ok(v) => v.parse().map_err(|reason| ConfigError::Invalid {
+                    var: "TILE_ON_MISSING",
+                    reason,
+                })?,
+                Err(_) => OnMissing::default(),
+            },
+            tile_content_types: match std::env::var("TILE_CONTENT_TYPES") {
+                Ok(v) => parse_content_types(&v).map_err(|reason| ConfigError::Invalid {
+                    var: "TILE_CONTENT_TYPES",
+                    reason,
+                })?,
+                Err(_) => BTreeMap::new(),
+            },
+            slow_marker_query: Duration::from_millis(env_number(
+                "SLOW_MARKER_QUERY_MS",
+                DEFAULT_SLOW_MARKER_QUERY_MS,
+            )?),
+            slow_tile_fetch: Duration::from_millis(env_number(
+                "SLOW_TILE_FETCH_MS",
+                DEFAULT_SLOW_TILE_FETCH_MS,
+            )?),
+            request_timeout: Some(env_number("REQUEST_TIMEOUT_SECS", 0)?)
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
+            max_connections: Some(env_number("MAX_CONNECTIONS", 0)?).filter(|&n| n > 0),
+            published: match std::env::var("TILE_PUBLISHED_WINDOWS") {
+                Ok(v) => parse_published(&v).map_err(|reason| ConfigError::Invalid {
+                    var: "TILE_PUBLISHED_WINDOWS",
+                    reason,
+                })?,
+                Err(_) => BTreeMap::new(),
+            },
+        })
+    }
+
+    /// `Content-Type` for a tile: the configured override for its format, or
+    /// the standard type.
+    pub fn tile_content_type<'a>(&'a self, id: &TileId) -> &'a str {
+        self.tile_content_types
+            .get(&id.ext)
+            .map_or(id.mime(), String::as_str)
+    }
+
+    /// Whether `id` falls inside its map's published window (if it has one).
+    pub fn is_published(&self, id: &TileId) -> bool {
+        self.published
+            .get(&id.prefix)
+            .is_none_or(|w| w.allows(TileCoord::new(id.z, id.x, id.y)))
+    }
+
+    /// `Cache-Control` for a tile at zoom `z`. Zooms below the lowest
+    /// configured key keep the immutable default.
+    pub fn tile_cache_control(&self, z: u32) -> String {
+        match self.tile_max_age.range(..=z).next_back() {
+            Some((_, max_age)) => format!("public, max-age={max_age}"),
+            None => IMMUTABLE_TILE_CACHE_CONTROL.to_string(),
+        }
+    }
+}
+
+/// Placeholder emitted instead of a configured secret.
+pub const REDACTED: &str = "<redacted>";
+
+fn redacted<S: Serializer>(secret: &Option<String>, s: S) -> Result<S::Ok, S::Error> {
+    secret.as_ref().map(|_| REDACTED).serialize(s)
+}
+
+fn millis<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u128(d.as_millis())
+}
+
+fn opt_millis<S: Serializer>(d: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+    d.map(|d| d.as_millis()).serialize(s)
+}
+
+/// Parse the `TILE_PUBLISHED_WINDOWS` JSON, rejecting inverted ranges.
+fn parse_published(s: &str) -> Result<BTreeMap<String, PublishedWindow>, String> {
+    let windows: BTreeMap<String, PublishedWindow> =
+        serde_json::from_str(s).map_err(|e| e.to_string())?;
+    for (prefix, w) in &windows {
+        if w.min_zoom > w.max_zoom {
+            return Err(format!("window for {prefix:?} has min_zoom > max_zoom"));
+        }
+        if w.region.is_some_and(|r| !r.is_valid()) {
+            return Err(format!(
+                "region for {prefix:?} has min_x > max_x or min_y > max_y"
+            ));
+        }
+        if w.region.is_some_and(|r| r.z as usize > MAX_QUADKEY_LEN) {
+            return Err(format!(
+                "region zoom for {prefix:?} above {MAX_QUADKEY_LEN}"
+            ));
+        }
+    }
+    Ok(windows)
+}
+
+/// A plain number from `var`, or `default` when unset.
+fn env_number<T: FromStr>(var: &'static str, default: T) -> Result<T, ConfigError> {
+    match std::env::var(var) {
+        Ok(v) => v.trim().parse().map_err(|_| ConfigError::Invalid {
+            var,
+            reason: format!("not a number: {v:?}"),
+        }),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Parse `format=type,...`. Formats must be ones we serve; types must be
+/// usable as a header value.
+fn parse_content_types(s: &str) -> Result<BTreeMap<String, String>, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (ext, ty) = p
+                .split_once('=')
+                .ok_or_else(|| format!("expected format=type, got {p:?}"))?;
+            let (ext, ty) = (ext.trim(), ty.trim());
+            if ext != "webp" && ext != "png" {
+                return Err(format!("unknown tile format {ext:?}"));
+            }
+            if !ty.contains('/') || !ty.bytes().all(|b| b.is_ascii_graphic() || b == b' ') {
+                return Err(format!("not a media type: {ty:?}"));
+            }
+            Ok((ext.to_string(), ty.to_string()))
+        })
+        .collect()
+}
+
+/// Parse `zoom:value,zoom:value,...` (e.g. `0:604800,8:3600`).
+fn parse_zoom_map(s: &str) -> Result<BTreeMap<u32, u32>, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (z, v) = p
+                .split_once(':')
+                .ok_or_else(|| format!("expected zoom:value, got {p:?}"))?;
+            let z = z.trim().parse().map_err(|_| format!("bad zoom in {p:?}"))?;
+            let v = v
+                .trim()
+                .parse()
+                .map_err(|_| format!("bad value in {p:?}"))?;
+            Ok((z, v))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zoom_map_parses_and_rejects_garbage() {
+        let m = parse_zoom_map(" 0:604800, 8:3600 ,").unwrap();
+        assert_eq!(m, BTreeMap::from([(0, 604800), (8, 3600)]));
+        assert!(parse_zoom_map("").unwrap().is_empty());
+        assert!(parse_zoom_map("0=60").is_err());
+        assert!(parse_zoom_map("x:60").is_err());
+        assert!(parse_zoom_map("0:-1").is_err());
+    }
+
+    #[test]
+    fn content_type_overrides_are_validated() {
+        let m = parse_content_types("webp=image/png, png = image/vnd.legacy-png").unwrap();
+        assert_eq!(m["webp"], "image/png");
+        assert_eq!(m["png"], "image/vnd.legacy-png");
+        assert!(parse_content_types("jpg=image/jpeg").is_err());
+        assert!(parse_content_types("webp=png").is_err());
+        assert!(parse_content_types("webp=image/\u{7f}png").is_err());
+    }
+
+    #[test]
+    fn published_windows_parse_and_validate() {
+        let w = parse_published(
+            r#"{"demo/world": {"min_zoom": 0, "max_zoom": 3,
+                "region": {"z": 3, "min_x": 2, "min_y": 1, "max_x": 5, "max_y": 4}}}"#,
+        )
+        .unwrap();
+        assert_eq!(w["demo/world"].max_zoom, 3);
+        let zooms = parse_published(r#"{"m": {"min_zoom": 4, "max_zoom": 1}}"#).unwrap_err();
+        assert!(zooms.contains("min_zoom > max_zoom"), "{zooms}");
+        let region = parse_published(
+            r#"{"m": {"min_zoom": 0, "max_zoom": 3,
+                "region": {"z": 3, "min_x": 5, "min_y": 1, "max_x": 2, "max_y": 4}}}"#,
+        )
+        .unwrap_err();
+        assert!(region.starts_with("region for \"m\""), "{region}");
+        assert!(parse_published("demo/world:0-3").is_err());
+        assert!(parse_published(
+            r#"{"m": {"min_zoom": 0, "max_zoom": 1,
+                "region": {"z": 99, "min_x": 0, "min_y": 0, "max_x": 1, "max_y": 1}}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn cache_control_picks_the_band_containing_the_zoom() {
+        let cfg = ServeConfig {
+            tile_max_age: BTreeMap::from([(2, 604800), (6, 3600)]),
+            ..Default::default()
+        };
+        assert_eq!(cfg.tile_cache_control(0), IMMUTABLE_TILE_CACHE_CONTROL);
+        assert_eq!(cfg.tile_cache_control(2), "public, max-age=604800");
+        assert_eq!(cfg.tile_cache_control(5), "public, max-age=604800");
+        assert_eq!(cfg.tile_cache_control(9), "public, max-age=3600");
+        assert_eq!(
+            ServeConfig::default().tile_cache_control(9),
+            IMMUTABLE_TILE_CACHE_CONTROL
+        );
+    }
+
+    #[test]
+    fn on_missing_parses_and_refuses_generate() {
+        assert_eq!("blank".parse::<OnMissing>(), Ok(OnMissing::Blank));
+        assert_eq!(" not_found ".parse::<OnMissing>(), Ok(OnMissing::NotFound));
+        assert!("generate".parse::<OnMissing>().is_err());
+        assert!("404".parse::<OnMissing>().is_err());
+    }
+}
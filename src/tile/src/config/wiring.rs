@@ -0,0 +1,168 @@
+//! [`WiringConfig`]: the variables `main.rs` assembles the service from.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::{Serialize, Serializer};
+
+use super::{env_number, ConfigError, REDACTED};
+use crate::selftest::StartupMode;
+use crate::tiles::DigestAlgorithm;
+
+/// Default `BIND_ADDR`.
+pub const DEFAULT_BIND_ADDR: &str = "0.0.0.0:8080";
+
+/// Default `TILE_CACHE_MB`.
+pub const DEFAULT_TILE_CACHE_MB: u64 = 256;
+
+/// How the service is wired together: which database and tile origin, cache
+/// sizes, startup behaviour. Read once by `main.rs`; URL passwords are masked
+/// when serialized.
+#[derive(Debug, Clone, Serialize)]
+pub struct WiringConfig {
+    /// `DATABASE_URL` (required).
+    #[serde(serialize_with = "masked_url")]
+    pub database_url: String,
+    /// `TILE_ORIGIN`: `local:/path` or an http(s) base URL (required).
+    #[serde(serialize_with = "masked_url")]
+    pub tile_origin: String,
+    /// `BIND_ADDR`.
+    pub bind_addr: String,
+    /// In-process tile cache budget in MiB (`TILE_CACHE_MB`).
+    pub tile_cache_mb: u64,
+    /// Algorithm for the tile `Digest` header (`TILE_DIGEST`).
+    #[serde(serialize_with = "digest_name")]
+    pub tile_digest: DigestAlgorithm,
+    /// Disk tier root in front of an http origin (`TILE_DISK_CACHE`).
+    pub tile_disk_cache: Option<PathBuf>,
+    /// Retries of throttled http origin reads (`TILE_ORIGIN_RETRIES`).
+    pub tile_origin_retries: u32,
+    /// Boot-time self-test mode (`STARTUP_SELFTEST`).
+    pub startup_selftest: StartupMode,
+    /// `KAFKA_BROKERS` as given; unset = catalog.changed consumer off.
+    pub kafka_brokers: Option<String>,
+}
+
+impl Default for WiringConfig {
+    fn default() -> Self {
+        Self {
+            database_url: String::new(),
+            tile_origin: String::new(),
+            bind_addr: DEFAULT_BIND_ADDR.to_string(),
+            tile_cache_mb: DEFAULT_TILE_CACHE_MB,
+            tile_digest: DigestAlgorithm::default(),
+            tile_disk_cache: None,
+            tile_origin_retries: 0,
+            startup_selftest: StartupMode::default(),
+            kafka_brokers: None,
+        }
+    }
+}
+
+impl WiringConfig {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let required = |var| std::env::var(var).map_err(|_| ConfigError::Missing(var));
+        let cfg = Self {
+            database_url: required("DATABASE_URL")?,
+            tile_origin: required("TILE_ORIGIN")?,
+            bind_addr: std::env::var("BIND_ADDR").unwrap_or_else(|_| DEFAULT_BIND_ADDR.into()),
+            tile_cache_mb: env_number("TILE_CACHE_MB", DEFAULT_TILE_CACHE_MB)?,
+            tile_digest: env_parsed("TILE_DIGEST")?.unwrap_or_default(),
+            tile_disk_cache: std::env::var_os("TILE_DISK_CACHE").map(PathBuf::from),
+            tile_origin_retries: env_number("TILE_ORIGIN_RETRIES", 0)?,
+            startup_selftest: env_parsed("STARTUP_SELFTEST")?.unwrap_or_default(),
+            kafka_brokers: std::env::var("KAFKA_BROKERS").ok(),
+        };
+        if cfg.tile_disk_cache.is_some() && cfg.tile_origin.starts_with("local:") {
+            return Err(ConfigError::Invalid {
+                var: "TILE_DISK_CACHE",
+                reason: "only applies to an http TILE_ORIGIN".into(),
+            });
+        }
+        Ok(cfg)
+    }
+}
+
+/// `url` with its password replaced by [`REDACTED`]: the one in `user:pass@`
+/// and a libpq-style `password=` query parameter.
+pub fn mask_password(url: &str) -> String {
+    let mut out = url.to_string();
+    if let Some(scheme_end) = out.find("://") {
+        let start = scheme_end + 3;
+        let end = out[start..]
+            .find(['/', '?', '#'])
+            .map_or(out.len(), |i| start + i);
+        if let Some(at) = out[start..end].rfind('@') {
+            if let Some(colon) = out[start..start + at].find(':') {
+                out.replace_range(start + colon + 1..start + at, REDACTED);
+            }
+        }
+    }
+    if let Some((head, query)) = out.split_once('?') {
+        let query: Vec<String> = query
+            .split('&')
+            .map(|kv| match kv.split_once('=') {
+                Some((k, _)) if k.eq_ignore_ascii_case("password") => format!("{k}={REDACTED}"),
+                _ => kv.to_string(),
+            })
+            .collect();
+        out = format!("{head}?{}", query.join("&"));
+    }
+    out
+}
+
+/// `var` parsed with its `FromStr`, or `None` when unset.
+fn env_parsed<T>(var: &'static str) -> Result<Option<T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(var) {
+        Ok(v) => v
+            .parse()
+            .map(Some)
+            .map_err(|e: T::Err| ConfigError::Invalid {
+                var,
+                reason: e.to_string(),
+            }),
+        Err(_) => Ok(None),
+    }
+}
+
+fn masked_url<S: Serializer>(url: &str, s: S) -> Result<S::Ok, S::Error> {
+    mask_password(url).serialize(s)
+}
+
+fn digest_name<S: Serializer>(alg: &DigestAlgorithm, s: S) -> Result<S::Ok, S::Error> {
+    alg.name().serialize(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_passwords_are_masked() {
+        assert_eq!(
+            mask_password("postgres://app:hunter2@db:5432/maps"),
+            "postgres://app:<redacted>@db:5432/maps"
+        );
+        assert_eq!(
+            mask_password("postgres://db/maps?user=app&password=hunter2&sslmode=require"),
+            "postgres://db/maps?user=app&password=<redacted>&sslmode=require"
+        );
+        // Nothing to mask.
+        assert_eq!(
+            mask_password("postgres://app@db/maps"),
+            "postgres://app@db/maps"
+        );
+        assert_eq!(mask_password("local:/srv/tiles"), "local:/srv/tiles");
+
+        let wiring = WiringConfig {
+            database_url: "postgres://app:hunter2@db/maps".into(),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&wiring).unwrap();
+        assert!(!json.contains("hunter2"), "{json}");
+    }
+}
@@ -238,7 +238,7 @@ where
     match state.repo.prefix_for_map(map_id).await {
         Ok(Some(prefix)) => {
             tracing::info!(map_id, %prefix, "invalidating tile cache for re-tiled map");
-            state.tiles.invalidate_prefix(&prefix);
+            state.tiles.invalidate_prefix(&prefix).await;
         }
         Ok(None) => {
             tracing::debug!(
@@ -255,8 +255,9 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::ClusterConfig;
+    use crate::config::ServeConfig;
     use crate::events::catalog_v1::catalog_changed::Action;
+    use crate::http::tests::app_state;
     use crate::repo::{InMemoryRepo, MapMeta};
     use crate::tiles::{CachedTiles, TileError, TileId, TileOrigin};
     use bytes::Bytes;
@@ -283,6 +284,7 @@ mod tests {
                 width: 100,
                 height: 100,
                 max_zoom: 4,
+                tile_size: 256,
             },
             prefix: PREFIX.to_string(),
         };
@@ -299,11 +301,7 @@ mod tests {
             .unwrap();
         tiles.run_pending_for_test().await;
 
-        Arc::new(AppState {
-            repo,
-            tiles,
-            cluster_cfg: ClusterConfig::default(),
-        })
+        app_state(repo, tiles, ServeConfig::default())
     }
 
     /// Encode a CatalogChanged into a Kafka record (key = map_id string, value =
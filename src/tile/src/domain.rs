@@ -45,6 +45,19 @@ pub struct Marker {
     pub x: f64,
     pub y: f64,
     pub title: Option<String>,
+    /// Inclusive zoom range the marker is visible in; `None` = unbounded on
+    /// that side. The viewport query already applies it, so clients needn't.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_zoom: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_zoom: Option<i32>,
+}
+
+impl Marker {
+    /// Whether the marker's zoom range includes `zoom`.
+    pub fn visible_at(&self, zoom: i32) -> bool {
+        self.min_zoom.is_none_or(|min| zoom >= min) && self.max_zoom.is_none_or(|max| zoom <= max)
+    }
 }
 
 /// A server-side aggregation of nearby markers, returned at low zoom so the
@@ -0,0 +1,428 @@
+//! Pixel-space tile grid math.
+//!
+//! The tiler lays a map out as an XYZ pyramid over the map's *native pixels*
+//! (see `src/tiler/pyramid.py`): at `max_zoom` a tile covers `tile_size` native
+//! pixels per side, and each zoom step down halves the image, so a tile at zoom
+//! `z` covers `tile_size * 2^(max_zoom - z)` native pixels. Origin is top-left,
+//! y grows downward, and there is no wrap — game maps have no antimeridian.
+//!
+//! Everything here is arithmetic over that layout; nothing enumerates tiles, so
+//! it is safe to ask about regions with millions of them (the admin region
+//! invalidation sizes a request this way before walking it). [`TileCoord`] is
+//! the bare `(z, x, y)` address, with quadkey conversion for Bing-style tooling.
+
+use crate::domain::BBox;
+use crate::repo::MapMeta;
+
+/// Longest quadkey we accept: one digit per zoom level, and `x`/`y` must fit
+/// a `u32`. Real pyramids stop far below this (Bing itself stops at 23).
+pub const MAX_QUADKEY_LEN: usize = 30;
+
+/// A tile's `(z, x, y)` address, independent of map prefix and format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileCoord {
+    pub z: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QuadkeyError {
+    #[error("quadkey must be 1..={MAX_QUADKEY_LEN} digits")]
+    Length,
+    #[error("quadkey digit {0:?} is not 0-3")]
+    Digit(char),
+}
+
+impl TileCoord {
+    pub fn new(z: u32, x: u32, y: u32) -> Self {
+        Self { z, x, y }
+    }
+
+    /// Decode a Bing-style quadkey: one base-4 digit per level, most
+    /// significant first, each digit `x_bit | y_bit << 1`.
+    pub fn from_quadkey(quadkey: &str) -> Result<Self, QuadkeyError> {
+        if quadkey.is_empty() || quadkey.len() > MAX_QUADKEY_LEN {
+            return Err(QuadkeyError::Length);
+        }
+        let (mut x, mut y) = (0u32, 0u32);
+        for c in quadkey.chars() {
+            let d = match c {
+                '0'..='3' => c as u32 - '0' as u32,
+                _ => return Err(QuadkeyError::Digit(c)),
+            };
+            x = (x << 1) | (d & 1);
+            y = (y << 1) | (d >> 1);
+        }
+        Ok(Self::new(quadkey.len() as u32, x, y))
+    }
+
+    /// The tile one level up that contains this one; `None` at zoom 0.
+    pub fn parent(&self) -> Option<Self> {
+        let z = self.z.checked_sub(1)?;
+        Some(Self::new(z, self.x >> 1, self.y >> 1))
+    }
+
+    /// The four tiles one level down, in quadkey digit order (top-left,
+    /// top-right, bottom-left, bottom-right).
+    pub fn children(&self) -> [Self; 4] {
+        let (z, x, y) = (self.z + 1, self.x << 1, self.y << 1);
+        [
+            Self::new(z, x, y),
+            Self::new(z, x + 1, y),
+            Self::new(z, x, y + 1),
+            Self::new(z, x + 1, y + 1),
+        ]
+    }
+
+    /// Inverse of [`TileCoord::from_quadkey`]; zoom 0 encodes as `""`.
+    pub fn to_quadkey(&self) -> String {
+        (0..self.z)
+            .rev()
+            .map(|i| {
+                let d = ((self.x >> i) & 1) | (((self.y >> i) & 1) << 1);
+                char::from(b'0' + d as u8)
+            })
+            .collect()
+    }
+}
+
+/// An inclusive rectangle of tiles at one zoom, e.g. a published region.
+/// `z` must not exceed [`MAX_QUADKEY_LEN`] (the overlap math shifts by it).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct TileRange {
+    pub z: u32,
+    pub min_x: u32,
+    pub min_y: u32,
+    pub max_x: u32,
+    pub max_y: u32,
+}
+
+impl TileRange {
+    pub fn is_valid(&self) -> bool {
+        self.min_x <= self.max_x && self.min_y <= self.max_y
+    }
+
+    /// Whether tile `c` (at any zoom) covers part of this range's area:
+    /// deeper tiles must lie inside it, shallower ones must overlap it.
+    pub fn overlaps(&self, c: TileCoord) -> bool {
+        let within = |v: u64, lo: u32, hi: u32| (u64::from(lo)..=u64::from(hi)).contains(&v);
+        if c.z >= self.z {
+            let shift = c.z - self.z;
+            let down = |v: u32| u64::from(v.checked_shr(shift).unwrap_or(0));
+            within(down(c.x), self.min_x, self.max_x) && within(down(c.y), self.min_y, self.max_y)
+        } else {
+            let shift = self.z - c.z;
+            let span = |v: u32| {
+                let lo = u64::from(v) << shift;
+                (lo, lo + (1u64 << shift) - 1)
+            };
+            let ((x0, x1), (y0, y1)) = (span(c.x), span(c.y));
+            x0 <= u64::from(self.max_x)
+                && x1 >= u64::from(self.min_x)
+                && y0 <= u64::from(self.max_y)
+                && y1 >= u64::from(self.min_y)
+        }
+    }
+}
+
+/// The tile pyramid of one map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileGrid {
+    /// Native pixel dimensions (the size at `max_zoom`).
+    pub width: i64,
+    pub height: i64,
+    pub max_zoom: i32,
+    /// Tile edge in pixels (`maps.tile_size`, 256 unless the tiler was told
+    /// otherwise).
+    pub tile_size: u32,
+}
+
+impl TileGrid {
+    pub fn new(meta: &MapMeta) -> Self {
+        Self {
+            width: meta.width,
+            height: meta.height,
+            max_zoom: meta.max_zoom,
+            tile_size: meta.tile_size,
+        }
+    }
+
+    /// Native pixels covered by one tile edge at `z`, or `None` above
+    /// `max_zoom` (the tiler never builds over-zoomed levels) or when the
+    /// span doesn't fit a `u64` (no real image is that large).
+    pub fn tile_span(&self, z: u32) -> Option<u64> {
+        let exp = u32::try_from(self.max_zoom).ok()?.checked_sub(z)?;
+        1u64.checked_shl(exp)?
+            .checked_mul(u64::from(self.tile_size))
+    }
+
+    /// `(cols, rows)` of tiles at `z`; same result as the tiler's
+    /// `PyramidSpec.grid_dimensions`. `(0, 0)` for levels that don't exist.
+    pub fn dimensions(&self, z: u32) -> (u64, u64) {
+        match self.tile_span(z) {
+            Some(span) if span > 0 => (
+                (self.width.max(0) as u64).div_ceil(span).max(1),
+                (self.height.max(0) as u64).div_ceil(span).max(1),
+            ),
+            _ => (0, 0),
+        }
+    }
+
+    /// Native-pixel area of tile `c`, or `None` if the pyramid has no such
+    /// tile. The last column/row can extend past the image (the tiler pads
+    /// those tiles).
+    pub fn tile_bounds(&self, c: TileCoord) -> Option<BBox> {
+        let (cols, rows) = self.dimensions(c.z);
+        if u64::from(c.x) >= cols || u64::from(c.y) >= rows {
+            return None;
+        }
+        let span = self.tile_span(c.z)? as f64;
+        let (x, y) = (f64::from(c.x) * span, f64::from(c.y) * span);
+        Some(BBox::new(x, y, x + span, y + span))
+    }
+
+    /// The tile containing native pixel `(x, y)` at every zoom in
+    /// `min_zoom..=max_zoom` the pyramid has, lowest zoom first. Empty when
+    /// the point is off the map. A point on the right/bottom edge belongs to
+    /// the last column/row.
+    pub fn covering_tiles(&self, x: f64, y: f64, min_zoom: u32, max_zoom: u32) -> Vec<TileCoord> {
+        let on_map =
+            (0.0..=self.width as f64).contains(&x) && (0.0..=self.height as f64).contains(&y);
+        if !on_map {
+            return Vec::new();
+        }
+        (min_zoom..=max_zoom)
+            .map_while(|z| {
+                let span = self.tile_span(z)? as f64;
+                let (cols, rows) = self.dimensions(z);
+                let col = ((x / span) as u64).min(cols.saturating_sub(1));
+                let row = ((y / span) as u64).min(rows.saturating_sub(1));
+                Some(TileCoord::new(z, col as u32, row as u32))
+            })
+            .collect()
+    }
+
+    /// Number of tiles at `z` that overlap `bbox` (native pixel space).
+    ///
+    /// Computed from the covered column/row ranges, clamped to the grid, so
+    /// the cost is constant however large the region is. Invalid boxes and
+    /// boxes entirely off the map count zero.
+    pub fn count_in_bounds(&self, bbox: &BBox, z: u32) -> u64 {
+        let (cols, rows) = self.dimensions(z);
+        let Some(span) = self.tile_span(z) else {
+            return 0;
+        };
+        if cols == 0 || !bbox.is_valid() {
+            return 0;
+        }
+        let span = span as f64;
+        axis_count(bbox.min_x, bbox.max_x, span, cols)
+            * axis_count(bbox.min_y, bbox.max_y, span, rows)
+    }
+}
+
+/// Tiles `[i*span, (i+1)*span)` for `i in 0..n` that overlap `[min, max]`.
+fn axis_count(min: f64, max: f64, span: f64, n: u64) -> u64 {
+    let lo = (min / span).floor().max(0.0);
+    let hi = (max / span).floor().min(n as f64 - 1.0);
+    if hi < lo {
+        0
+    } else {
+        (hi - lo) as u64 + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(width: i64, height: i64, max_zoom: i32) -> TileGrid {
+        TileGrid {
+            width,
+            height,
+            max_zoom,
+            tile_size: 256,
+        }
+    }
+
+    /// Reference answer: walk every tile at `z` and test overlap directly.
+    fn brute_force(g: &TileGrid, bbox: &BBox, z: u32) -> u64 {
+        let (cols, rows) = g.dimensions(z);
+        let Some(span) = g.tile_span(z) else {
+            return 0;
+        };
+        let span = span as f64;
+        let overlaps = |i: u64, min: f64, max: f64| {
+            let start = i as f64 * span;
+            start <= max && start + span > min
+        };
+        let mut n = 0;
+        for ty in 0..rows {
+            for tx in 0..cols {
+                if overlaps(tx, bbox.min_x, bbox.max_x) && overlaps(ty, bbox.min_y, bbox.max_y) {
+                    n += 1;
+                }
+            }
+        }
+        n
+    }
+
+    #[test]
+    fn dimensions_match_the_tiler_pyramid() {
+        // 1000x600 at tile 256 -> max_zoom 2 in the tiler.
+        let g = grid(1000, 600, 2);
+        assert_eq!(g.dimensions(2), (4, 3));
+        assert_eq!(g.dimensions(1), (2, 2)); // 500x300
+        assert_eq!(g.dimensions(0), (1, 1)); // 250x150
+        assert_eq!(g.dimensions(3), (0, 0)); // not built
+    }
+
+    #[test]
+    fn count_matches_enumeration_for_small_regions() {
+        let g = grid(1000, 600, 2);
+        let boxes = [
+            BBox::new(0.0, 0.0, 1000.0, 600.0),
+            BBox::new(10.0, 10.0, 20.0, 20.0),
+            BBox::new(255.0, 0.0, 256.0, 300.0), // straddles a column edge
+            BBox::new(-500.0, -500.0, 100.0, 100.0), // partly off the map
+            BBox::new(2000.0, 2000.0, 3000.0, 3000.0), // fully off the map
+            BBox::new(500.0, 500.0, 500.0, 500.0), // a point
+        ];
+        for z in 0..=3 {
+            for b in &boxes {
+                assert_eq!(g.count_in_bounds(b, z), brute_force(&g, b, z), "{b:?} z{z}");
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_bbox_counts_zero() {
+        let g = grid(1000, 600, 2);
+        assert_eq!(g.count_in_bounds(&BBox::new(100.0, 0.0, 0.0, 10.0), 2), 0);
+    }
+
+    #[test]
+    fn huge_regions_are_counted_arithmetically() {
+        // 2^20 x 2^20 tiles at max zoom — far too many to enumerate.
+        let side = 256i64 << 20;
+        let g = grid(side, side, 20);
+        let all = BBox::new(0.0, 0.0, side as f64, side as f64);
+        assert_eq!(g.count_in_bounds(&all, 20), 1u64 << 40);
+        assert_eq!(g.count_in_bounds(&all, 0), 1);
+    }
+
+    #[test]
+    fn spans_too_wide_for_u64_are_none_not_wrapped() {
+        // 256 << 56 wraps to 0 if the shift is only clamped.
+        let g = grid(1000, 1000, 56);
+        assert_eq!(g.tile_span(1), Some(256 << 55));
+        assert_eq!(g.tile_span(0), None);
+        assert_eq!(grid(1000, 1000, 64).tile_span(0), None);
+        assert_eq!(g.dimensions(0), (0, 0));
+    }
+
+    #[test]
+    fn quadkey_round_trips() {
+        // Bing's documented example: tile 3/3/5 is "213".
+        assert_eq!(TileCoord::new(3, 3, 5).to_quadkey(), "213");
+        assert_eq!(TileCoord::from_quadkey("213"), Ok(TileCoord::new(3, 3, 5)));
+
+        for z in 1..=6 {
+            for x in 0..(1u32 << z) {
+                for y in [0, (1u32 << z) - 1, x % (1u32 << z)] {
+                    let c = TileCoord::new(z, x, y);
+                    assert_eq!(TileCoord::from_quadkey(&c.to_quadkey()), Ok(c));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bad_quadkeys_are_rejected() {
+        assert_eq!(TileCoord::from_quadkey(""), Err(QuadkeyError::Length));
+        assert_eq!(
+            TileCoord::from_quadkey(&"0".repeat(MAX_QUADKEY_LEN + 1)),
+            Err(QuadkeyError::Length)
+        );
+        assert_eq!(
+            TileCoord::from_quadkey("0142"),
+            Err(QuadkeyError::Digit('4'))
+        );
+        assert_eq!(TileCoord::from_quadkey("1a"), Err(QuadkeyError::Digit('a')));
+    }
+
+    #[test]
+    fn parent_and_children_are_inverse() {
+        assert_eq!(TileCoord::new(0, 0, 0).parent(), None);
+        assert_eq!(
+            TileCoord::new(0, 0, 0).children(),
+            [(1, 0, 0), (1, 1, 0), (1, 0, 1), (1, 1, 1)].map(|(z, x, y)| TileCoord::new(z, x, y))
+        );
+        assert_eq!(
+            TileCoord::new(3, 5, 2).parent(),
+            Some(TileCoord::new(2, 2, 1))
+        );
+
+        for z in 0..=4 {
+            for x in 0..(1u32 << z) {
+                for y in 0..(1u32 << z) {
+                    let c = TileCoord::new(z, x, y);
+                    for (digit, child) in c.children().into_iter().enumerate() {
+                        assert_eq!(child.parent(), Some(c));
+                        // Children extend the parent's quadkey by one digit.
+                        assert_eq!(child.to_quadkey(), format!("{}{digit}", c.to_quadkey()));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn covering_tiles_contain_the_point_at_every_zoom() {
+        let g = grid(1000, 600, 2);
+        for (x, y) in [(0.0, 0.0), (257.5, 10.0), (999.0, 599.0), (1000.0, 600.0)] {
+            let tiles = g.covering_tiles(x, y, 0, 10);
+            // Clipped to the pyramid: zooms 0..=2 only.
+            assert_eq!(tiles.iter().map(|t| t.z).collect::<Vec<_>>(), [0, 1, 2]);
+            for (t, parent) in tiles.iter().skip(1).zip(&tiles) {
+                assert_eq!(t.parent().as_ref(), Some(parent));
+            }
+            for t in tiles {
+                let b = g.tile_bounds(t).unwrap();
+                assert!(b.min_x <= x && x <= b.max_x && b.min_y <= y && y <= b.max_y);
+            }
+        }
+        assert_eq!(
+            g.covering_tiles(257.5, 10.0, 2, 2),
+            [TileCoord::new(2, 1, 0)]
+        );
+        assert!(g.covering_tiles(-1.0, 10.0, 0, 2).is_empty());
+        assert!(g.covering_tiles(10.0, 601.0, 0, 2).is_empty());
+        assert_eq!(g.tile_bounds(TileCoord::new(2, 4, 0)), None);
+    }
+
+    #[test]
+    fn tile_range_overlap_across_zooms() {
+        // Columns 2..=5, rows 1..=4 at zoom 3.
+        let r = TileRange {
+            z: 3,
+            min_x: 2,
+            min_y: 1,
+            max_x: 5,
+            max_y: 4,
+        };
+        assert!(r.overlaps(TileCoord::new(3, 2, 1)));
+        assert!(!r.overlaps(TileCoord::new(3, 6, 1)));
+        // Deeper: 4/11/9 sits in 3/5/4; 4/12/9 sits in 3/6/4.
+        assert!(r.overlaps(TileCoord::new(4, 11, 9)));
+        assert!(!r.overlaps(TileCoord::new(4, 12, 9)));
+        // Shallower: 1/0/0 covers 3/0..=3/0..=3, which overlaps; 2/3/3
+        // covers 3/6..=7/6..=7, which doesn't.
+        assert!(r.overlaps(TileCoord::new(1, 0, 0)));
+        assert!(!r.overlaps(TileCoord::new(2, 3, 3)));
+        assert!(r.overlaps(TileCoord::new(0, 0, 0)));
+        // Absurd zooms don't overflow.
+        assert!(!r.overlaps(TileCoord::new(200, u32::MAX, u32::MAX)));
+    }
+}
@@ -1,20 +1,34 @@
 //! HTTP layer (Axum): routes, handlers, query parsing, error mapping.
+//!
+//! Tile serving lives in the `tiles` submodule; this file keeps the router, the marker
+//! endpoint, and the shared error type.
+
+mod tiles;
+
+pub(crate) use tiles::parse_tile_path;
 
 use std::sync::Arc;
+use std::time::Instant;
 
 use axum::{
     extract::{Path, Query, State},
-    http::{header, HeaderMap, HeaderValue, StatusCode},
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     routing::get,
     Json, Router,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower_http::set_header::SetResponseHeaderLayer;
+use tower_http::timeout::TimeoutLayer;
 
 use crate::cluster::cluster_markers;
+use crate::config::{ServeConfig, WiringConfig};
 use crate::domain::{BBox, ClusterConfig, ViewportItems, ViewportQuery, ViewportResponse};
+use crate::metrics;
 use crate::repo::{MarkerRepo, RepoError};
-use crate::tiles::{CachedTiles, TileError, TileId, TileOrigin};
+use crate::selftest;
+use crate::tiles::{CachedTiles, TileOrigin};
 
 /// Shared application state. Generic over the repo + tile origin so tests can
 /// substitute in-memory implementations.
@@ -22,16 +36,101 @@ pub struct AppState<R: MarkerRepo, O: TileOrigin> {
     pub repo: R,
     pub tiles: CachedTiles<O>,
     pub cluster_cfg: ClusterConfig,
+    pub config: ServeConfig,
+    pub wiring: WiringConfig,
 }
 
 pub type SharedState<R, O> = Arc<AppState<R, O>>;
 
 pub fn router<R: MarkerRepo, O: TileOrigin>(state: SharedState<R, O>) -> Router {
-    Router::new()
-        .route("/healthz", get(|| async { "ok" }))
+    let mut app = Router::new()
         .route("/maps/{map_id}/markers", get(viewport_handler::<R, O>))
-        .route("/tiles/{*tile}", get(tile_handler::<R, O>))
-        .with_state(state)
+        // `get` also answers HEAD: axum runs the handler (a cache hit for hot
+        // tiles) and strips the body, keeping Content-Length and the rest of
+        // the headers — what monitoring wants for existence/size checks.
+        .route("/tiles/{*tile}", get(tiles::tile_handler::<R, O>));
+    // Admin routes exist only when a token is configured; the gateway never
+    // proxies `/admin`, so this is reachable from inside the cluster only.
+    if state.config.admin_token.is_some() {
+        app = app.merge(crate::admin::routes::<R, O>(state.config.admin_body_limit));
+    }
+    // One semaphore shared by every route above. The probes are added after
+    // it, so a saturated instance still answers liveness and metrics.
+    if let Some(max) = state.config.max_connections {
+        app = app.layer(GlobalConcurrencyLimitLayer::new(max));
+    }
+    app = app
+        .route("/healthz", get(|| async { "ok" }))
+        .route("/readyz", get(readiness_handler::<R, O>))
+        .route("/version", get(version_handler))
+        .route("/metrics", get(|| async { metrics::render() }));
+    if let Some(timeout) = state.config.request_timeout {
+        app = app.layer(TimeoutLayer::with_status_code(
+            StatusCode::REQUEST_TIMEOUT,
+            timeout,
+        ));
+    }
+    app.with_state(state)
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::SERVER,
+            HeaderValue::from_static(SERVER_HEADER),
+        ))
+}
+
+// ---- build info --------------------------------------------------------------
+
+/// `Server` header on every response, so a curl tells you what's deployed.
+const SERVER_HEADER: &str = concat!("tile-service/", env!("CARGO_PKG_VERSION"));
+
+/// Build provenance returned by `GET /version`; the SHA and timestamp are
+/// stamped in by `build.rs`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    /// Unix seconds when the binary was built.
+    pub build_timestamp: u64,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("TILE_GIT_SHA"),
+            build_timestamp: env!("TILE_BUILD_TIMESTAMP").parse().unwrap_or(0),
+        }
+    }
+}
+
+async fn version_handler() -> Json<BuildInfo> {
+    Json(BuildInfo::current())
+}
+
+// ---- readiness ---------------------------------------------------------------
+
+/// `GET /readyz`: the database and tile origin checks of the startup
+/// self-test, run on demand (the origin one as a cheap existence lookup).
+/// `/healthz` only says the process is up; this says whether it can serve, so
+/// a misconfigured bucket shows up before the first tile request does. 503
+/// when any check fails.
+async fn readiness_handler<R: MarkerRepo, O: TileOrigin>(
+    State(state): State<SharedState<R, O>>,
+) -> Response {
+    let report = selftest::readiness(&state).await;
+    let check = |r: &Result<(), String>| match r {
+        Ok(()) => "ok".to_string(),
+        Err(e) => e.clone(),
+    };
+    let status = if report.passed() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = serde_json::json!({
+        "ready": report.passed(),
+        "checks": { "repo": check(&report.repo), "tiles": check(&report.tiles) },
+    });
+    (status, Json(body)).into_response()
 }
 
 // ---- viewport query ----------------------------------------------------------
@@ -50,6 +149,8 @@ pub struct ViewportParams {
 pub enum ApiError {
     #[error("bad request: {0}")]
     BadRequest(String),
+    #[error("unauthorized")]
+    Unauthorized,
     #[error("not found")]
     NotFound,
     #[error("internal error")]
@@ -67,6 +168,7 @@ impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         let (code, msg) = match self {
             ApiError::BadRequest(m) => (StatusCode::BAD_REQUEST, m),
+            ApiError::Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized".into()),
             ApiError::NotFound => (StatusCode::NOT_FOUND, "not found".into()),
             ApiError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "internal error".into()),
         };
@@ -75,7 +177,7 @@ impl IntoResponse for ApiError {
 }
 
 /// Parse `minx,miny,maxx,maxy` into a validated [`BBox`].
-fn parse_bbox(s: &str) -> Result<BBox, ApiError> {
+pub(crate) fn parse_bbox(s: &str) -> Result<BBox, ApiError> {
     let parts: Vec<&str> = s.split(',').collect();
     if parts.len() != 4 {
         return Err(ApiError::BadRequest(
@@ -132,8 +234,23 @@ async fn viewport_handler<R: MarkerRepo, O: TileOrigin>(
         .await?
         .ok_or(ApiError::NotFound)?;
 
+    let started = Instant::now();
     let resp =
         build_viewport_response(&state.repo, &query, meta.max_zoom, &state.cluster_cfg).await?;
+    let elapsed = started.elapsed();
+    if elapsed > state.config.slow_marker_query {
+        metrics::SLOW_OPERATIONS
+            .with_label_values(&["marker_query"])
+            .inc();
+        tracing::warn!(
+            map_id,
+            zoom = query.zoom,
+            bbox = ?query.bbox,
+            total = resp.total,
+            elapsed_ms = elapsed.as_millis() as u64,
+            "slow marker query"
+        );
+    }
     Ok(Json(resp))
 }
 
@@ -174,98 +291,5 @@ pub async fn build_viewport_response<R: MarkerRepo>(
     }
 }
 
-// ---- tile serving ------------------------------------------------------------
-
-async fn tile_handler<R: MarkerRepo, O: TileOrigin>(
-    State(state): State<SharedState<R, O>>,
-    Path(tile): Path<String>,
-) -> Result<Response, ApiError> {
-    // `tile` is "<prefix...>/<z>/<x>/<y>.<ext>"; the prefix may contain slashes,
-    // so split the fixed trailing components off the right.
-    let parts: Vec<&str> = tile.rsplitn(4, '/').collect();
-    // rsplitn yields right-to-left: [ "y.ext", "x", "z", "<prefix>" ]
-    if parts.len() != 4 {
-        return Err(ApiError::BadRequest(
-            "tile path must be <prefix>/<z>/<x>/<y>.<ext>".into(),
-        ));
-    }
-    let y_ext = parts[0];
-    let x: u32 = parts[1]
-        .parse()
-        .map_err(|_| ApiError::BadRequest("tile x not a number".into()))?;
-    let z: u32 = parts[2]
-        .parse()
-        .map_err(|_| ApiError::BadRequest("tile z not a number".into()))?;
-    let prefix = parts[3].to_string();
-
-    let (y_str, ext) = y_ext
-        .rsplit_once('.')
-        .ok_or_else(|| ApiError::BadRequest("tile must end in .webp or .png".into()))?;
-    let y: u32 = y_str
-        .parse()
-        .map_err(|_| ApiError::BadRequest("tile y not a number".into()))?;
-    if ext != "webp" && ext != "png" {
-        return Err(ApiError::BadRequest("unsupported tile extension".into()));
-    }
-
-    let id = TileId {
-        prefix,
-        z,
-        x,
-        y,
-        ext: ext.to_string(),
-    };
-    let mime = id.mime();
-
-    match state.tiles.get(id).await {
-        Ok(bytes) => {
-            let mut headers = HeaderMap::new();
-            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(mime));
-            // Tiles are immutable; let the CDN + browser hold them forever.
-            headers.insert(
-                header::CACHE_CONTROL,
-                HeaderValue::from_static("public, max-age=31536000, immutable"),
-            );
-            Ok((StatusCode::OK, headers, bytes).into_response())
-        }
-        // Blank tiles are skipped at tiling time, so misses are expected; a
-        // 404 lets MapLibre treat them as transparent.
-        Err(TileError::NotFound) => Err(ApiError::NotFound),
-        Err(e) => {
-            tracing::error!(error = %e, "tile origin error");
-            Err(ApiError::Internal)
-        }
-    }
-}
-
 #[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn parse_bbox_ok() {
-        let b = parse_bbox("0,0,100,200").unwrap();
-        assert_eq!(b, BBox::new(0.0, 0.0, 100.0, 200.0));
-    }
-
-    #[test]
-    fn parse_bbox_rejects_bad_shapes() {
-        assert!(parse_bbox("1,2,3").is_err());
-        assert!(parse_bbox("a,b,c,d").is_err());
-        assert!(parse_bbox("100,100,0,0").is_err()); // max < min
-    }
-
-    #[test]
-    fn parse_categories_variants() {
-        assert_eq!(parse_categories(&None).unwrap(), Vec::<i64>::new());
-        assert_eq!(
-            parse_categories(&Some("".into())).unwrap(),
-            Vec::<i64>::new()
-        );
-        assert_eq!(
-            parse_categories(&Some("1,2,3".into())).unwrap(),
-            vec![1i64, 2, 3]
-        );
-        assert!(parse_categories(&Some("1,x".into())).is_err());
-    }
-}
+pub(crate) mod tests;
@@ -0,0 +1,337 @@
+//! Tests for the router and the shared test fixtures (split out to keep `http.rs` readable).
+
+use super::*;
+use crate::repo::{InMemoryRepo, MapMeta};
+use crate::tiles::{TileError, TileId};
+use bytes::Bytes;
+
+/// Origin that serves the same bytes for every key.
+pub(crate) struct StaticOrigin;
+#[async_trait::async_trait]
+impl TileOrigin for StaticOrigin {
+    async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
+        Ok(Bytes::from_static(b"tile-bytes"))
+    }
+}
+
+/// Origin with no tiles at all; counts how often it is asked.
+#[derive(Default)]
+pub(crate) struct EmptyOrigin(pub(crate) std::sync::atomic::AtomicUsize);
+#[async_trait::async_trait]
+impl TileOrigin for EmptyOrigin {
+    async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Err(TileError::NotFound)
+    }
+}
+
+pub(crate) fn test_repo() -> InMemoryRepo {
+    InMemoryRepo {
+        markers: Vec::new(),
+        markers_map_id: 1,
+        meta: MapMeta {
+            width: 100,
+            height: 100,
+            max_zoom: 4,
+            tile_size: 256,
+        },
+        prefix: "m".into(),
+    }
+}
+
+pub(crate) fn app_state<R: MarkerRepo, O: TileOrigin>(
+    repo: R,
+    tiles: CachedTiles<O>,
+    config: ServeConfig,
+) -> SharedState<R, O> {
+    Arc::new(AppState {
+        repo,
+        tiles,
+        cluster_cfg: ClusterConfig::default(),
+        config,
+        wiring: WiringConfig::default(),
+    })
+}
+
+/// [`test_repo`] state serving from `origin` through a 1 MiB cache.
+pub(crate) fn state_with<O: TileOrigin>(
+    origin: O,
+    config: ServeConfig,
+) -> SharedState<InMemoryRepo, O> {
+    app_state(test_repo(), CachedTiles::new(origin, 1024 * 1024), config)
+}
+
+pub(crate) fn test_state() -> SharedState<InMemoryRepo, StaticOrigin> {
+    state_with(StaticOrigin, ServeConfig::default())
+}
+
+#[test]
+fn parse_bbox_ok() {
+    let b = parse_bbox("0,0,100,200").unwrap();
+    assert_eq!(b, BBox::new(0.0, 0.0, 100.0, 200.0));
+}
+
+#[test]
+fn parse_bbox_rejects_bad_shapes() {
+    assert!(parse_bbox("1,2,3").is_err());
+    assert!(parse_bbox("a,b,c,d").is_err());
+    assert!(parse_bbox("100,100,0,0").is_err()); // max < min
+}
+
+#[test]
+fn parse_categories_variants() {
+    assert_eq!(parse_categories(&None).unwrap(), Vec::<i64>::new());
+    assert_eq!(
+        parse_categories(&Some("".into())).unwrap(),
+        Vec::<i64>::new()
+    );
+    assert_eq!(
+        parse_categories(&Some("1,2,3".into())).unwrap(),
+        vec![1i64, 2, 3]
+    );
+    assert!(parse_categories(&Some("1,x".into())).is_err());
+}
+
+#[tokio::test]
+async fn version_reports_the_compiled_in_build() {
+    let Json(info) = version_handler().await;
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    // A checkout stamps a real SHA; the hermetic test image has no `.git` and
+    // no GIT_SHA, so build.rs falls back to "unknown" there.
+    assert!(
+        info.git_sha == "unknown"
+            || (info.git_sha.len() >= 7 && info.git_sha.chars().all(|c| c.is_ascii_hexdigit())),
+        "{}",
+        info.git_sha
+    );
+    assert!(info.build_timestamp > 0);
+    assert_eq!(SERVER_HEADER, format!("tile-service/{}", info.version));
+}
+
+#[tokio::test]
+async fn markers_outside_their_zoom_range_are_hidden() {
+    use crate::domain::Marker;
+
+    let marker = |id, min_zoom| Marker {
+        id,
+        category_id: 1,
+        x: 10.0,
+        y: 10.0,
+        title: None,
+        min_zoom,
+        max_zoom: None,
+    };
+    let mut state = test_state();
+    Arc::get_mut(&mut state).unwrap().repo.markers = vec![marker(1, None), marker(2, Some(3))];
+
+    let ids_at = |zoom| {
+        let state = Arc::clone(&state);
+        async move {
+            let q = ViewportQuery {
+                map_id: 1,
+                bbox: BBox::new(0.0, 0.0, 100.0, 100.0),
+                zoom,
+                categories: Vec::new(),
+            };
+            let resp = build_viewport_response(&state.repo, &q, 4, &state.cluster_cfg)
+                .await
+                .unwrap();
+            match resp.items {
+                ViewportItems::Markers { markers } => {
+                    markers.iter().map(|m| m.id).collect::<Vec<_>>()
+                }
+                ViewportItems::Clusters { .. } => panic!("unexpected clustering"),
+            }
+        }
+    };
+    assert_eq!(ids_at(1).await, vec![1]);
+    let mut high = ids_at(4).await;
+    high.sort();
+    assert_eq!(high, vec![1, 2]);
+}
+
+#[tokio::test]
+async fn readyz_turns_503_when_the_tile_origin_goes_away() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Empty origin that can be switched off.
+    struct Switchable(Arc<AtomicBool>);
+    #[async_trait::async_trait]
+    impl TileOrigin for Switchable {
+        async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
+            if self.0.load(Ordering::SeqCst) {
+                Err(TileError::NotFound)
+            } else {
+                Err(TileError::Io("bucket does not exist".into()))
+            }
+        }
+    }
+    let up = Arc::new(AtomicBool::new(true));
+    let state = state_with(Switchable(Arc::clone(&up)), ServeConfig::default());
+    let probe = || async {
+        let resp = readiness_handler(State(Arc::clone(&state))).await;
+        let status = resp.status();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+
+    let (status, body) = probe().await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["checks"]["tiles"], "ok");
+
+    // The earlier probe must not be answered from the cache.
+    up.store(false, Ordering::SeqCst);
+    let (status, body) = probe().await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["ready"], false);
+    assert_eq!(body["checks"]["repo"], "ok");
+    assert!(body["checks"]["tiles"]
+        .as_str()
+        .unwrap()
+        .contains("bucket does not exist"));
+}
+
+#[tokio::test]
+async fn configured_request_timeout_answers_408() {
+    use axum::body::Body;
+    use axum::http::Request;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    /// Origin that never answers.
+    struct Hung;
+    #[async_trait::async_trait]
+    impl TileOrigin for Hung {
+        async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
+            std::future::pending().await
+        }
+    }
+    let state = state_with(
+        Hung,
+        ServeConfig {
+            request_timeout: Some(Duration::from_millis(20)),
+            ..Default::default()
+        },
+    );
+    let resp = router(state)
+        .oneshot(
+            Request::get("/tiles/m/0/0/0.webp")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::REQUEST_TIMEOUT);
+}
+
+#[tokio::test]
+async fn max_connections_queues_requests_over_the_limit() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use axum::http::Request;
+    use tokio::sync::Semaphore;
+    use tower::ServiceExt;
+
+    /// Origin that counts reads and holds each one until the gate opens.
+    struct Gated(Arc<AtomicUsize>, Arc<Semaphore>);
+    #[async_trait::async_trait]
+    impl TileOrigin for Gated {
+        async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let _permit = self.1.acquire().await.unwrap();
+            Ok(Bytes::from_static(b"tile"))
+        }
+    }
+    let (entered, gate) = (Arc::new(AtomicUsize::new(0)), Arc::new(Semaphore::new(0)));
+    let app = router(state_with(
+        Gated(Arc::clone(&entered), Arc::clone(&gate)),
+        ServeConfig {
+            max_connections: Some(1),
+            ..Default::default()
+        },
+    ));
+    let get = |path: &'static str| {
+        let app = app.clone();
+        tokio::spawn(async move {
+            let req = Request::get(path).body(Body::empty()).unwrap();
+            app.oneshot(req).await.unwrap().status()
+        })
+    };
+
+    let first = get("/tiles/m/0/0/0.webp");
+    while entered.load(Ordering::SeqCst) == 0 {
+        tokio::task::yield_now().await;
+    }
+    let second = get("/tiles/m/1/0/0.webp");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(
+        entered.load(Ordering::SeqCst),
+        1,
+        "second request got a slot"
+    );
+    assert_eq!(get("/healthz").await.unwrap(), StatusCode::OK);
+
+    gate.add_permits(2);
+    assert_eq!(first.await.unwrap(), StatusCode::OK);
+    assert_eq!(second.await.unwrap(), StatusCode::OK);
+    assert_eq!(entered.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn slow_marker_query_is_counted() {
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    use crate::domain::Marker;
+
+    /// [`test_repo`] with a count that takes a while.
+    struct Sluggish(InMemoryRepo);
+    #[async_trait::async_trait]
+    impl MarkerRepo for Sluggish {
+        async fn count_in_viewport(&self, q: &ViewportQuery) -> Result<i64, RepoError> {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.0.count_in_viewport(q).await
+        }
+        async fn markers_in_viewport(
+            &self,
+            q: &ViewportQuery,
+            limit: i64,
+        ) -> Result<Vec<Marker>, RepoError> {
+            self.0.markers_in_viewport(q, limit).await
+        }
+        async fn map_meta(&self, map_id: i64) -> Result<Option<MapMeta>, RepoError> {
+            self.0.map_meta(map_id).await
+        }
+        async fn prefix_for_map(&self, map_id: i64) -> Result<Option<String>, RepoError> {
+            self.0.prefix_for_map(map_id).await
+        }
+    }
+    let state = app_state(
+        Sluggish(test_repo()),
+        CachedTiles::new(StaticOrigin, 1024 * 1024),
+        ServeConfig {
+            slow_marker_query: Duration::from_millis(5),
+            ..Default::default()
+        },
+    );
+    let slow = metrics::SLOW_OPERATIONS.with_label_values(&["marker_query"]);
+    let before = slow.get();
+
+    let req = Request::get("/maps/1/markers?bbox=0,0,100,100&zoom=1")
+        .body(Body::empty())
+        .unwrap();
+    let resp = router(state).oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    // Other tests share the global counter, so only a lower bound holds.
+    assert!(slow.get() > before);
+}
@@ -0,0 +1,212 @@
+//! `GET /tiles/{*tile}`: path parsing and the cached tile response.
+
+use std::time::Instant;
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+
+use super::{ApiError, SharedState};
+use crate::config::OnMissing;
+use crate::grid::{TileCoord, MAX_QUADKEY_LEN};
+use crate::metrics::{InFlight, IN_FLIGHT_REQUESTS, SLOW_OPERATIONS, TILE_SIZE_BYTES};
+use crate::repo::MarkerRepo;
+use crate::tiles::{Tile, TileError, TileId, TileOrigin};
+
+/// RFC 3230 instance digest; not in `http::header`'s standard set.
+const DIGEST: header::HeaderName = header::HeaderName::from_static("digest");
+
+/// 1x1 transparent placeholders for `TILE_ON_MISSING=blank`; clients stretch
+/// them over the tile.
+const BLANK_PNG: &[u8] = include_bytes!("tiles/blank.png");
+const BLANK_WEBP: &[u8] = include_bytes!("tiles/blank.webp");
+
+/// ETags for misses. Every miss of one mode has the same body, so a fixed tag
+/// per mode validates it; blank 200s also answer `If-None-Match` with a 304.
+const MISS_ETAG: HeaderValue = HeaderValue::from_static("\"miss\"");
+const BLANK_ETAG: HeaderValue = HeaderValue::from_static("\"blank\"");
+
+/// Parse a tile path into a [`TileId`]. Two shapes are accepted:
+///
+///   * `<prefix>/<z>/<x>/<y>.<ext>` — the tiling pipeline layout;
+///   * `<prefix>/quadkey/<quadkey>.<ext>` — Bing-style addressing, decoded to
+///     the same `(z, x, y)` so both shapes share one cache entry.
+///
+/// The prefix may contain slashes, so the fixed trailing components are split
+/// off the right. Zooms above [`MAX_QUADKEY_LEN`] are refused in both shapes: no
+/// pyramid has them, and walking up from one (`ancestors` invalidation)
+/// would loop that many times.
+pub(crate) fn parse_tile_path(tile: &str) -> Result<TileId, ApiError> {
+    if let Some((prefix, file)) = tile.rsplit_once('/') {
+        if let Some(prefix) = prefix.strip_suffix("/quadkey") {
+            let (quadkey, ext) = split_tile_ext(file)?;
+            let c = TileCoord::from_quadkey(quadkey)
+                .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+            return Ok(TileId {
+                prefix: prefix.to_string(),
+                z: c.z,
+                x: c.x,
+                y: c.y,
+                ext: ext.to_string(),
+            });
+        }
+    }
+
+    let parts: Vec<&str> = tile.rsplitn(4, '/').collect();
+    // rsplitn yields right-to-left: [ "y.ext", "x", "z", "<prefix>" ]
+    if parts.len() != 4 {
+        return Err(ApiError::BadRequest(
+            "tile path must be <prefix>/<z>/<x>/<y>.<ext>".into(),
+        ));
+    }
+    let x: u32 = parts[1]
+        .parse()
+        .map_err(|_| ApiError::BadRequest("tile x not a number".into()))?;
+    let z: u32 = parts[2]
+        .parse()
+        .map_err(|_| ApiError::BadRequest("tile z not a number".into()))?;
+    if z as usize > MAX_QUADKEY_LEN {
+        return Err(ApiError::BadRequest(format!(
+            "tile z above {MAX_QUADKEY_LEN}"
+        )));
+    }
+    let prefix = parts[3].to_string();
+
+    let (y_str, ext) = split_tile_ext(parts[0])?;
+    let y: u32 = y_str
+        .parse()
+        .map_err(|_| ApiError::BadRequest("tile y not a number".into()))?;
+
+    Ok(TileId {
+        prefix,
+        z,
+        x,
+        y,
+        ext: ext.to_string(),
+    })
+}
+
+/// Split `<stem>.<ext>`, accepting only the formats the tiler writes.
+fn split_tile_ext(file: &str) -> Result<(&str, &str), ApiError> {
+    let (stem, ext) = file
+        .rsplit_once('.')
+        .ok_or_else(|| ApiError::BadRequest("tile must end in .webp or .png".into()))?;
+    if ext != "webp" && ext != "png" {
+        return Err(ApiError::BadRequest("unsupported tile extension".into()));
+    }
+    Ok((stem, ext))
+}
+
+pub(super) async fn tile_handler<R: MarkerRepo, O: TileOrigin>(
+    State(state): State<SharedState<R, O>>,
+    Path(tile): Path<String>,
+    request_headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let _in_flight = InFlight::track(&IN_FLIGHT_REQUESTS);
+    let id = parse_tile_path(&tile)?;
+    let content_type = HeaderValue::from_str(state.config.tile_content_type(&id))
+        .map_err(|_| ApiError::Internal)?;
+    let cache_control = HeaderValue::from_str(&state.config.tile_cache_control(id.z))
+        .map_err(|_| ApiError::Internal)?;
+
+    let size_labels = [id.ext.clone(), id.z.to_string()];
+
+    // Unpublished tiles are never fetched, and are answered as misses: same
+    // status, Cache-Control and TILE_ON_MISSING handling as an absent tile.
+    let result = if state.config.is_published(&id) {
+        let started = Instant::now();
+        let result = state.tiles.get(id.clone()).await;
+        let elapsed = started.elapsed();
+        if elapsed > state.config.slow_tile_fetch {
+            SLOW_OPERATIONS.with_label_values(&["tile_fetch"]).inc();
+            tracing::warn!(
+                tile = %id.key(),
+                elapsed_ms = elapsed.as_millis() as u64,
+                "slow tile fetch"
+            );
+        }
+        result
+    } else {
+        Err(TileError::NotFound)
+    };
+
+    match result {
+        Ok(tile) => {
+            TILE_SIZE_BYTES
+                .with_label_values(&size_labels)
+                .observe(tile.bytes.len() as f64);
+            // Immutable by default; TILE_MAX_AGE_BY_ZOOM shortens it per zoom.
+            tile_response(tile, content_type, cache_control)
+        }
+        // Blank tiles are skipped at tiling time, so misses are expected; a
+        // 404 lets MapLibre treat them as transparent. The miss is cached
+        // in-process already; a short max-age lets the CDN and browser hold it
+        // too, so panning over empty areas doesn't come back here at all.
+        // Origin errors (below) stay uncacheable.
+        Err(TileError::NotFound) => {
+            let cache_control = format!("public, max-age={}", state.config.tile_miss_max_age);
+            let cache_control =
+                HeaderValue::from_str(&cache_control).map_err(|_| ApiError::Internal)?;
+            match state.config.on_missing {
+                OnMissing::NotFound => {
+                    let mut resp = ApiError::NotFound.into_response();
+                    resp.headers_mut()
+                        .insert(header::CACHE_CONTROL, cache_control);
+                    resp.headers_mut().insert(header::ETAG, MISS_ETAG);
+                    Ok(resp)
+                }
+                OnMissing::Blank
+                    if request_headers.get(header::IF_NONE_MATCH) == Some(&BLANK_ETAG) =>
+                {
+                    let headers = [
+                        (header::CACHE_CONTROL, cache_control),
+                        (header::ETAG, BLANK_ETAG),
+                    ];
+                    Ok((StatusCode::NOT_MODIFIED, headers).into_response())
+                }
+                OnMissing::Blank => {
+                    let blank = if id.ext == "png" {
+                        BLANK_PNG
+                    } else {
+                        BLANK_WEBP
+                    };
+                    let tile = Tile::new(Bytes::from_static(blank), state.tiles.digest_algorithm());
+                    let mut resp = tile_response(tile, content_type, cache_control)?;
+                    resp.headers_mut().insert(header::ETAG, BLANK_ETAG);
+                    Ok(resp)
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "tile origin error");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+/// A 200 carrying `tile`, with the headers every served tile gets.
+fn tile_response(
+    tile: Tile,
+    content_type: HeaderValue,
+    cache_control: HeaderValue,
+) -> Result<Response, ApiError> {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, content_type);
+    headers.insert(header::CACHE_CONTROL, cache_control);
+    // Computed once at fetch time and cached with the bytes, so clients
+    // holding tiles long-term can verify them against any response.
+    let digest = HeaderValue::from_str(&tile.digest).map_err(|_| ApiError::Internal)?;
+    headers.insert(DIGEST, digest);
+    // Explicit rather than left to the body's size hint, so every path
+    // (GET, HEAD, cache hit or miss) agrees; and tiles are small opaque
+    // blobs, so tell proxies/CDNs not to bother with range requests.
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(tile.bytes.len()));
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("none"));
+    Ok((StatusCode::OK, headers, tile.bytes).into_response())
+}
+
+#[cfg(test)]
+mod tests;
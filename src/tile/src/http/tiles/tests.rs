@@ -0,0 +1,419 @@
+//! Tests for the tile handler (split out to keep `tiles.rs` readable).
+
+use std::sync::Arc;
+
+use super::*;
+use crate::config::ServeConfig;
+use crate::http::router;
+use crate::http::tests::{app_state, state_with, test_repo, test_state, EmptyOrigin, StaticOrigin};
+use crate::metrics;
+use crate::repo::InMemoryRepo;
+use crate::tiles::CachedTiles;
+use bytes::Bytes;
+
+/// Drive the tile handler directly (no router), mapping errors the way
+/// axum would.
+async fn get_tile<O: TileOrigin>(state: &SharedState<InMemoryRepo, O>, path: &str) -> Response {
+    tile_handler(
+        State(Arc::clone(state)),
+        Path(path.to_string()),
+        HeaderMap::new(),
+    )
+    .await
+    .unwrap_or_else(IntoResponse::into_response)
+}
+
+#[tokio::test]
+async fn tile_digest_matches_recomputed_body_hash() {
+    use base64::Engine;
+    use sha2::{Digest, Sha256};
+
+    let state = test_state();
+    // First response is fresh from the origin, the second is a cache hit;
+    // both must carry a digest of exactly the bytes they return.
+    for _ in 0..2 {
+        let resp = get_tile(&state, "m/0/0/0.webp").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let digest = resp.headers()[DIGEST].to_str().unwrap().to_string();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let expected = format!(
+            "sha-256={}",
+            base64::engine::general_purpose::STANDARD.encode(Sha256::digest(&body))
+        );
+        assert_eq!(digest, expected);
+    }
+}
+
+#[tokio::test]
+async fn head_tile_returns_get_headers_without_body() {
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    let app = router(test_state());
+    let get = app
+        .clone()
+        .oneshot(
+            Request::get("/tiles/m/0/0/0.webp")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let head = app
+        .oneshot(
+            Request::head("/tiles/m/0/0/0.webp")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(head.status(), StatusCode::OK);
+    for name in [
+        header::CONTENT_TYPE,
+        header::CONTENT_LENGTH,
+        header::CACHE_CONTROL,
+        DIGEST,
+    ] {
+        assert_eq!(
+            head.headers().get(&name),
+            get.headers().get(&name),
+            "{name}"
+        );
+    }
+    assert_eq!(head.headers()[header::CONTENT_LENGTH], "10");
+    let body = axum::body::to_bytes(head.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert!(body.is_empty());
+}
+
+#[test]
+fn parse_tile_path_shapes() {
+    let id = parse_tile_path("elden-ring/overworld/3/3/5.webp").unwrap();
+    assert_eq!(id.key(), "elden-ring/overworld/3/3/5.webp");
+
+    // "213" is 3/3/5: same TileId (and cache entry) as the xyz form.
+    let qk = parse_tile_path("elden-ring/overworld/quadkey/213.webp").unwrap();
+    assert_eq!(qk, id);
+
+    assert!(parse_tile_path("0/0/0.webp").is_err()); // no prefix
+    assert!(parse_tile_path("m/0/0/0.jpg").is_err());
+    assert!(parse_tile_path("m/quadkey/.webp").is_err());
+    assert!(parse_tile_path("m/30/0/0.webp").is_ok());
+    assert!(parse_tile_path("m/31/0/0.webp").is_err());
+    assert!(parse_tile_path("m/4294967295/0/0.webp").is_err());
+}
+
+#[tokio::test]
+async fn bad_quadkey_is_a_400() {
+    let state = test_state();
+    let resp = get_tile(&state, "m/quadkey/0142.webp").await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = get_tile(&state, "m/quadkey/0123.webp").await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn in_flight_gauge_covers_a_slow_tile_request() {
+    /// Origin that blocks until the test lets it go.
+    struct Gated(Arc<tokio::sync::Notify>);
+    #[async_trait::async_trait]
+    impl TileOrigin for Gated {
+        async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
+            self.0.notified().await;
+            Ok(Bytes::from_static(b"slow"))
+        }
+    }
+
+    let gate = Arc::new(tokio::sync::Notify::new());
+    let state = state_with(Gated(Arc::clone(&gate)), ServeConfig::default());
+    let req = tokio::spawn(tile_handler(
+        State(state),
+        Path("m/0/0/0.webp".into()),
+        HeaderMap::new(),
+    ));
+
+    // Other tests share the global gauge, so only a lower bound is exact.
+    while IN_FLIGHT_REQUESTS.get() < 1 {
+        tokio::task::yield_now().await;
+    }
+    assert!(metrics::render().contains("tile_in_flight_requests"));
+    gate.notify_one();
+    assert_eq!(req.await.unwrap().unwrap().status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn tile_response_sets_content_length_and_refuses_ranges() {
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    let app = router(test_state());
+    // Twice: the miss and the cache hit go down the same header path.
+    for _ in 0..2 {
+        let resp = app
+            .clone()
+            .oneshot(
+                Request::get("/tiles/m/1/0/1.webp")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()[header::ACCEPT_RANGES], "none");
+        let len: usize = resp.headers()[header::CONTENT_LENGTH]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(len, body.len());
+    }
+}
+
+#[tokio::test]
+async fn cache_control_max_age_follows_the_zoom_map() {
+    let state = state_with(
+        StaticOrigin,
+        ServeConfig {
+            tile_max_age: [(0, 604800), (4, 3600)].into(),
+            ..Default::default()
+        },
+    );
+    let cache_control = |path: &'static str| {
+        let state = Arc::clone(&state);
+        async move { get_tile(&state, path).await.headers()[header::CACHE_CONTROL].clone() }
+    };
+    assert_eq!(
+        cache_control("m/1/0/0.webp").await,
+        "public, max-age=604800"
+    );
+    assert_eq!(cache_control("m/5/0/0.webp").await, "public, max-age=3600");
+
+    let resp = get_tile(&test_state(), "m/5/0/0.webp").await;
+    assert_eq!(
+        resp.headers()[header::CACHE_CONTROL],
+        "public, max-age=31536000, immutable"
+    );
+}
+
+#[tokio::test]
+async fn served_tile_sizes_land_in_their_buckets() {
+    use prometheus::core::Metric;
+
+    /// Origin whose tile at column `x` is `x` KiB.
+    struct Sized;
+    #[async_trait::async_trait]
+    impl TileOrigin for Sized {
+        async fn get(&self, id: &TileId) -> Result<Bytes, TileError> {
+            Ok(Bytes::from(vec![0u8; id.x as usize * 1024]))
+        }
+    }
+    let state = state_with(Sized, ServeConfig::default());
+    // Zoom 29 is used by no other test, so the global series is ours.
+    for path in [
+        "m/29/0/0.png",
+        "m/29/2/0.png",
+        "m/29/300/0.png",
+        "m/29/2/1.png",
+    ] {
+        tile_handler(
+            State(Arc::clone(&state)),
+            Path(path.into()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+    }
+
+    let h = TILE_SIZE_BYTES.with_label_values(&["png", "29"]).metric();
+    let h = h.get_histogram();
+    assert_eq!(h.get_sample_count(), 4);
+    let cumulative = |le: f64| {
+        h.get_bucket()
+            .iter()
+            .find(|b| b.upper_bound() == le)
+            .unwrap()
+            .cumulative_count()
+    };
+    assert_eq!(cumulative(128.0), 1); // the empty tile
+    assert_eq!(cumulative(512.0), 1);
+    assert_eq!(cumulative(2048.0), 3); // + two 2 KiB tiles (le is inclusive)
+    assert_eq!(cumulative(131072.0), 3);
+    assert_eq!(cumulative(524288.0), 4); // the 300 KiB tile
+    assert!(metrics::render().contains("tile_size_bytes_bucket"));
+}
+
+#[tokio::test]
+async fn empty_area_misses_are_cacheable_and_served_from_cache() {
+    use std::sync::atomic::Ordering;
+
+    let state = state_with(EmptyOrigin::default(), ServeConfig::default());
+
+    for _ in 0..2 {
+        let resp = tile_handler(
+            State(Arc::clone(&state)),
+            Path("m/3/1/1.webp".into()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(resp.headers()[header::CACHE_CONTROL], "public, max-age=300");
+        assert_eq!(resp.headers()[header::ETAG], "\"miss\"");
+    }
+    assert_eq!(state.tiles.origin().0.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn on_missing_blank_serves_a_transparent_placeholder() {
+    let state_for = |on_missing| {
+        state_with(
+            EmptyOrigin::default(),
+            ServeConfig {
+                on_missing,
+                ..Default::default()
+            },
+        )
+    };
+
+    let resp = get_tile(&state_for(OnMissing::NotFound), "m/3/1/1.webp").await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let state = state_for(OnMissing::Blank);
+    for (path, mime, magic) in [
+        ("m/3/1/1.webp", "image/webp", &b"RIFF"[..]),
+        ("m/3/1/1.png", "image/png", &b"\x89PNG"[..]),
+    ] {
+        let resp = get_tile(&state, path).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], mime);
+        assert_eq!(resp.headers()[header::CACHE_CONTROL], "public, max-age=300");
+        assert_eq!(resp.headers()[header::ACCEPT_RANGES], "none");
+        let length = resp.headers()[header::CONTENT_LENGTH].clone();
+        let digest = resp.headers()[DIGEST].to_str().unwrap().to_string();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.starts_with(magic));
+        assert_eq!(length, body.len().to_string());
+        assert_eq!(
+            digest,
+            crate::tiles::DigestAlgorithm::default().digest(&body)
+        );
+    }
+
+    // A client holding the placeholder revalidates it with a 304.
+    let mut headers = HeaderMap::new();
+    headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"blank\""));
+    let resp = tile_handler(State(state), Path("m/3/1/1.webp".into()), headers)
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(resp.headers()[header::ETAG], "\"blank\"");
+    assert_eq!(resp.headers()[header::CACHE_CONTROL], "public, max-age=300");
+}
+
+#[tokio::test]
+async fn content_type_override_replaces_the_standard_type() {
+    let state = state_with(
+        StaticOrigin,
+        ServeConfig {
+            tile_content_types: [("webp".to_string(), "image/png".to_string())].into(),
+            ..Default::default()
+        },
+    );
+    let resp = get_tile(&state, "m/0/0/0.webp").await;
+    assert_eq!(resp.headers()[header::CONTENT_TYPE], "image/png");
+    let resp = get_tile(&state, "m/0/0/0.png").await;
+    assert_eq!(resp.headers()[header::CONTENT_TYPE], "image/png");
+
+    let resp = get_tile(&test_state(), "m/0/0/0.webp").await;
+    assert_eq!(resp.headers()[header::CONTENT_TYPE], "image/webp");
+}
+
+#[tokio::test]
+async fn slow_tile_fetch_is_counted() {
+    use std::time::Duration;
+
+    /// Origin that takes a while to answer.
+    struct Sluggish;
+    #[async_trait::async_trait]
+    impl TileOrigin for Sluggish {
+        async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(Bytes::from_static(b"late"))
+        }
+    }
+    let state = state_with(
+        Sluggish,
+        ServeConfig {
+            slow_tile_fetch: Duration::from_millis(5),
+            ..Default::default()
+        },
+    );
+    let slow = SLOW_OPERATIONS.with_label_values(&["tile_fetch"]);
+    let before = slow.get();
+
+    let resp = get_tile(&state, "m/0/0/0.webp").await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    // Other tests share the global counter, so only a lower bound holds.
+    assert!(slow.get() > before);
+}
+
+#[tokio::test]
+async fn digest_header_uses_the_configured_algorithm() {
+    let tiles = CachedTiles::new(StaticOrigin, 1024 * 1024)
+        .with_digest(crate::tiles::DigestAlgorithm::Sha512);
+    let state = app_state(test_repo(), tiles, ServeConfig::default());
+    let resp = get_tile(&state, "m/0/0/0.webp").await;
+    let digest = resp.headers()[DIGEST].to_str().unwrap();
+    assert!(digest.starts_with("sha-512="), "{digest}");
+}
+
+#[tokio::test]
+async fn tiles_outside_the_published_window_404() {
+    let window = r#"{"m": {"min_zoom": 1, "max_zoom": 3,
+        "region": {"z": 3, "min_x": 2, "min_y": 1, "max_x": 5, "max_y": 4}}}"#;
+    let state = state_with(
+        StaticOrigin,
+        ServeConfig {
+            published: serde_json::from_str(window).unwrap(),
+            ..Default::default()
+        },
+    );
+    for (path, want) in [
+        ("m/3/2/1.webp", StatusCode::OK),
+        ("m/1/0/0.webp", StatusCode::OK),
+        ("m/3/6/1.webp", StatusCode::NOT_FOUND), // outside the region
+        ("m/0/0/0.webp", StatusCode::NOT_FOUND), // below min_zoom
+        ("m/4/4/2.webp", StatusCode::NOT_FOUND), // above max_zoom
+        ("other/4/4/2.webp", StatusCode::OK),    // no window: all public
+    ] {
+        assert_eq!(get_tile(&state, path).await.status(), want, "{path}");
+    }
+
+    // Unpublished is answered exactly like a miss.
+    let resp = get_tile(&state, "m/0/0/0.webp").await;
+    assert_eq!(resp.headers()[header::CACHE_CONTROL], "public, max-age=300");
+    let state = state_with(
+        StaticOrigin,
+        ServeConfig {
+            published: serde_json::from_str(window).unwrap(),
+            on_missing: OnMissing::Blank,
+            ..Default::default()
+        },
+    );
+    let resp = get_tile(&state, "m/0/0/0.webp").await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert!(body.starts_with(b"RIFF"));
+}
@@ -3,10 +3,15 @@
 //! Serves immutable map tiles (cached) and answers viewport marker queries
 //! against PostGIS, clustering server-side when a viewport is dense.
 
+pub mod admin;
 pub mod cluster;
+pub mod config;
 pub mod consumer;
 pub mod domain;
 pub mod events;
+pub mod grid;
 pub mod http;
+pub mod metrics;
 pub mod repo;
+pub mod selftest;
 pub mod tiles;
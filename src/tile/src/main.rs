@@ -5,68 +5,124 @@
 //!   TILE_ORIGIN    local:/path/to/tiles  |  http://cdn-or-bucket/base   (required)
 //!   BIND_ADDR      default 0.0.0.0:8080
 //!   TILE_CACHE_MB  in-process tile cache budget, default 256
+//!   TILE_ORIGIN_RETRIES  retries of 429/503 from an http TILE_ORIGIN, after its
+//!                     Retry-After (default 0)
+//!   TILE_DISK_CACHE  /path — local disk tier in front of an http TILE_ORIGIN;
+//!                     misses fill it, so restarts come back warm (unset = off).
+//!                     Unbounded, and a purge empties it: use a dedicated
+//!                     directory. Rejected with a `local:` TILE_ORIGIN
+//!   TILE_DIGEST    sha-256 (default) | sha-512 — algorithm for the Digest header
+//!   STARTUP_SELFTEST  off (default) | warn | strict — probe DB + origin + cache
+//!                     once at boot; `strict` refuses to start if it fails
+//!   ADMIN_TOKEN    bearer token for /admin/* (unset = admin routes not mounted)
+//!   ADMIN_BODY_LIMIT_BYTES  max /admin/* request body, 413 above (default 16384)
+//!   TILE_MAX_AGE_BY_ZOOM  e.g. `0:604800,8:3600` — tile max-age from each zoom
+//!                     up; unset = `max-age=31536000, immutable` everywhere
+//!   TILE_MISS_MAX_AGE  max-age (s) on tile 404s, and how long the service
+//!                     itself caches a miss; default 300
+//!   TILE_ON_MISSING  not_found (default) | blank — 404 or a transparent 200
+//!                     for tiles the origin lacks
+//!   TILE_CONTENT_TYPES  e.g. `webp=image/png` — per-format Content-Type override
+//!   SLOW_MARKER_QUERY_MS / SLOW_TILE_FETCH_MS  warn + count above these
+//!                     (defaults 250 / 500)
+//!   REQUEST_TIMEOUT_SECS  per-request deadline, 408 on overrun (unset/0 = none)
+//!   MAX_CONNECTIONS  requests served at once; the rest queue (unset/0 = no
+//!                     limit). Health, version and metrics probes are exempt
+//!   TILE_PUBLISHED_WINDOWS  JSON {prefix: {min_zoom, max_zoom, region?}} —
+//!                     serve only that part of a map; other tiles are misses
 
 use std::sync::Arc;
+use std::time::Duration;
 
+use tile_service::config::{ServeConfig, WiringConfig};
 use tile_service::domain::ClusterConfig;
 use tile_service::http::{router, AppState};
 use tile_service::repo::PgMarkerRepo;
-use tile_service::tiles::{CachedTiles, HttpTileOrigin, LocalTileOrigin, TileOrigin};
+use tile_service::selftest::StartupMode;
+use tile_service::tiles::{
+    CachedTiles, HttpTileOrigin, LocalTileOrigin, TieredTileOrigin, TileOrigin,
+};
 
 use tower_http::trace::TraceLayer;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Info by default; RUST_LOG=info,tile_service=debug adds a line per tile
+    // read with its cache key and storage path/URI.
     tracing_subscriber::fmt()
         .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "info,tile_service=debug".into()),
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
         )
         .init();
 
-    let db_url = std::env::var("DATABASE_URL")?;
-    let origin_spec = std::env::var("TILE_ORIGIN")?;
-    let bind = std::env::var("BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:8080".into());
-    let cache_mb: u64 = std::env::var("TILE_CACHE_MB")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(256);
+    let wiring = WiringConfig::from_env()?;
 
     let pool = sqlx::postgres::PgPoolOptions::new()
         .max_connections(16)
-        .connect(&db_url)
+        .connect(&wiring.database_url)
         .await?;
     let repo = PgMarkerRepo::new(pool);
+    let postgis = repo.ensure_postgis().await?;
+    tracing::info!(%postgis, "postgis extension present");
 
     // One generic AppState type per origin kind; pick at startup.
-    if let Some(path) = origin_spec.strip_prefix("local:") {
-        let tiles = CachedTiles::new(LocalTileOrigin::new(path), cache_mb * 1024 * 1024);
-        serve(repo, tiles, &bind).await
+    let cache_bytes = wiring.tile_cache_mb * 1024 * 1024;
+    let http =
+        || HttpTileOrigin::new(wiring.tile_origin.clone()).with_retries(wiring.tile_origin_retries);
+    if let Some(path) = wiring.tile_origin.strip_prefix("local:") {
+        let tiles = CachedTiles::new(LocalTileOrigin::new(path), cache_bytes);
+        serve(repo, tiles, wiring).await
+    } else if let Some(disk) = &wiring.tile_disk_cache {
+        let tiles = CachedTiles::new(TieredTileOrigin::new(disk, http()), cache_bytes);
+        serve(repo, tiles, wiring).await
     } else {
-        let tiles = CachedTiles::new(HttpTileOrigin::new(origin_spec), cache_mb * 1024 * 1024);
-        serve(repo, tiles, &bind).await
+        let tiles = CachedTiles::new(http(), cache_bytes);
+        serve(repo, tiles, wiring).await
     }
 }
 
 async fn serve<O: TileOrigin>(
     repo: PgMarkerRepo,
     tiles: CachedTiles<O>,
-    bind: &str,
+    wiring: WiringConfig,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let config = ServeConfig::from_env()?;
+    // The in-process miss lives as long as the edge is told to keep it.
+    let tiles = tiles
+        .with_digest(wiring.tile_digest)
+        .with_miss_ttl(Duration::from_secs(config.tile_miss_max_age.into()));
+    let selftest = wiring.startup_selftest;
     let state = Arc::new(AppState {
         repo,
         tiles,
         cluster_cfg: ClusterConfig::default(),
+        config,
+        wiring,
     });
 
+    // Optional end-to-end probe of the wiring (see selftest.rs), so a bad
+    // DATABASE_URL / TILE_ORIGIN surfaces at deploy time, not on first request.
+    if selftest != StartupMode::Off {
+        let report = tile_service::selftest::run(&state).await;
+        if report.passed() {
+            tracing::info!("startup self-test passed");
+        } else {
+            tracing::error!(repo = ?report.repo, tiles = ?report.tiles, "startup self-test failed");
+            if selftest == StartupMode::Strict {
+                return Err("startup self-test failed (STARTUP_SELFTEST=strict)".into());
+            }
+        }
+    }
+
     // Optional background consumer that invalidates the tile cache on catalog
     // re-tiles. Gated on KAFKA_BROKERS; never blocks or fails startup, and is a
     // no-op (logged once) when no broker is configured.
     tile_service::consumer::spawn_if_configured(Arc::clone(&state));
 
+    let bind = state.wiring.bind_addr.clone();
     let app = router(state).layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind(bind).await?;
+    let listener = tokio::net::TcpListener::bind(&bind).await?;
     tracing::info!("tile-service listening on {bind}");
     axum::serve(listener, app)
         .with_graceful_shutdown(shutdown_signal())
@@ -0,0 +1,97 @@
+//! Prometheus metrics, exposed as text at `GET /metrics`.
+//!
+//! Metrics live in the process-wide default registry (one service per
+//! process), registered lazily on first use.
+
+use std::sync::LazyLock;
+
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    Encoder, HistogramVec, IntCounterVec, IntGauge, TextEncoder,
+};
+
+/// Tile requests currently being handled. Held up by an [`InFlight`] guard, so
+/// it comes back down on every exit path: success, error, panic, or the client
+/// going away mid-request (the handler future is dropped).
+pub static IN_FLIGHT_REQUESTS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "tile_in_flight_requests",
+        "Tile requests currently being handled"
+    )
+    .expect("register tile_in_flight_requests")
+});
+
+/// Byte size of each served tile, by format and zoom. Buckets run 128 B
+/// (near-blank edge tiles) to 2 MiB (dense max-zoom detail) in 4x steps.
+/// Zoom is a bounded label: pyramids stop well before 30 levels.
+pub static TILE_SIZE_BYTES: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "tile_size_bytes",
+        "Byte size of served tiles",
+        &["format", "zoom"],
+        exponential_buckets(128.0, 4.0, 8).expect("static bucket layout")
+    )
+    .expect("register tile_size_bytes")
+});
+
+/// Operations that overran their slow threshold, by `op` (`marker_query`,
+/// `tile_fetch`). Each one is also logged at warn with its context.
+pub static SLOW_OPERATIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "tile_slow_operations_total",
+        "Operations slower than their configured threshold",
+        &["op"]
+    )
+    .expect("register tile_slow_operations_total")
+});
+
+/// RAII increment of a gauge; decremented on drop.
+#[must_use = "the gauge is decremented as soon as the guard is dropped"]
+pub struct InFlight<'a>(&'a IntGauge);
+
+impl<'a> InFlight<'a> {
+    pub fn track(gauge: &'a IntGauge) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Every registered metric in the Prometheus text exposition format.
+pub fn render() -> String {
+    let mut buf = Vec::new();
+    // Encoding into a Vec can only fail on a malformed metric family, which
+    // the register_* macros already rule out.
+    let _ = TextEncoder::new().encode(&prometheus::gather(), &mut buf);
+    String::from_utf8(buf).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_flight_guard_decrements_on_every_exit_path() {
+        // A private gauge, so parallel tests touching the global one can't
+        // interfere with the exact counts.
+        let gauge = IntGauge::new("t_in_flight", "test").unwrap();
+        {
+            let _a = InFlight::track(&gauge);
+            let _b = InFlight::track(&gauge);
+            assert_eq!(gauge.get(), 2);
+        }
+        assert_eq!(gauge.get(), 0);
+
+        let panicked = std::panic::catch_unwind(|| {
+            let _g = InFlight::track(&gauge);
+            panic!("handler blew up");
+        });
+        assert!(panicked.is_err());
+        assert_eq!(gauge.get(), 0);
+    }
+}
@@ -10,6 +10,8 @@
 //!   map_id      BIGINT NOT NULL,
 //!   category_id BIGINT NOT NULL,   -- FK to categories.id (BIGSERIAL): decode as i64
 //!   title       TEXT,
+//!   min_zoom    INT,               -- visible zoom range (inclusive), NULL = unbounded
+//!   max_zoom    INT,
 //!   -- pixel-space point; SRID 0 = "no CRS", which is correct for game maps
 //!   geom        geometry(Point, 0) NOT NULL
 //! );
@@ -18,6 +20,12 @@
 //! ```
 //!
 //! The bounding-box filter uses the `&&` operator, which is index-accelerated.
+//! Every query also drops markers whose `min_zoom..=max_zoom` excludes the
+//! requested zoom, so low zooms declutter without client-side filtering.
+//!
+//! Markers are stored only as `geom` (no raw coordinate columns), so a
+//! database without the `postgis` extension can't serve them at all;
+//! [`PgMarkerRepo::ensure_postgis`] fails fast at startup instead.
 
 use async_trait::async_trait;
 
@@ -28,6 +36,12 @@ use crate::domain::{Marker, ViewportQuery};
 pub enum RepoError {
     #[error("database error: {0}")]
     Db(#[from] sqlx::Error),
+    #[error(
+        "the postgis extension is not installed in this database; run \
+         `CREATE EXTENSION postgis;` as its owner, or point DATABASE_URL at a \
+         PostGIS-enabled instance"
+    )]
+    PostgisMissing,
 }
 
 /// What the read path needs from storage. Intentionally tiny.
@@ -59,6 +73,8 @@ pub struct MapMeta {
     pub width: i64,
     pub height: i64,
     pub max_zoom: i32,
+    /// Tile edge in pixels (`maps.tile_size`, default 256).
+    pub tile_size: u32,
 }
 
 /// PostGIS-backed implementation.
@@ -70,6 +86,21 @@ impl PgMarkerRepo {
     pub fn new(pool: sqlx::PgPool) -> Self {
         Self { pool }
     }
+
+    /// Check for the `postgis` extension and return its version. Without it
+    /// every marker query fails with "function st_makeenvelope does not
+    /// exist", which says nothing about the fix.
+    pub async fn ensure_postgis(&self) -> Result<String, RepoError> {
+        let version: Option<(String,)> =
+            sqlx::query_as("SELECT extversion FROM pg_extension WHERE extname = 'postgis'")
+                .fetch_optional(&self.pool)
+                .await?;
+        require_postgis(version.map(|(v,)| v))
+    }
+}
+
+fn require_postgis(installed: Option<String>) -> Result<String, RepoError> {
+    installed.ok_or(RepoError::PostgisMissing)
 }
 
 #[async_trait]
@@ -81,20 +112,25 @@ impl MarkerRepo for PgMarkerRepo {
         let row: (i64,) = if q.categories.is_empty() {
             sqlx::query_as(
                 "SELECT COUNT(*) FROM markers \
-                 WHERE map_id = $1 AND geom && ST_MakeEnvelope($2, $3, $4, $5, 0)",
+                 WHERE map_id = $1 AND geom && ST_MakeEnvelope($2, $3, $4, $5, 0) \
+                 AND (min_zoom IS NULL OR min_zoom <= $6) \
+                 AND (max_zoom IS NULL OR max_zoom >= $6)",
             )
             .bind(q.map_id)
             .bind(b.min_x)
             .bind(b.min_y)
             .bind(b.max_x)
             .bind(b.max_y)
+            .bind(q.zoom)
             .fetch_one(&self.pool)
             .await?
         } else {
             sqlx::query_as(
                 "SELECT COUNT(*) FROM markers \
                  WHERE map_id = $1 AND category_id = ANY($2) \
-                 AND geom && ST_MakeEnvelope($3, $4, $5, $6, 0)",
+                 AND geom && ST_MakeEnvelope($3, $4, $5, $6, 0) \
+                 AND (min_zoom IS NULL OR min_zoom <= $7) \
+                 AND (max_zoom IS NULL OR max_zoom >= $7)",
             )
             .bind(q.map_id)
             .bind(&q.categories)
@@ -102,6 +138,7 @@ impl MarkerRepo for PgMarkerRepo {
             .bind(b.min_y)
             .bind(b.max_x)
             .bind(b.max_y)
+            .bind(q.zoom)
             .fetch_one(&self.pool)
             .await?
         };
@@ -121,9 +158,12 @@ impl MarkerRepo for PgMarkerRepo {
         // the SQL stays a &'static str (sqlx 0.9 SqlSafeStr requirement).
         let rows: Vec<MarkerRow> = if q.categories.is_empty() {
             sqlx::query_as(
-                "SELECT id, category_id, ST_X(geom) AS x, ST_Y(geom) AS y, title \
+                "SELECT id, category_id, ST_X(geom) AS x, ST_Y(geom) AS y, title, \
+                        min_zoom, max_zoom \
                  FROM markers \
                  WHERE map_id = $1 AND geom && ST_MakeEnvelope($2, $3, $4, $5, 0) \
+                 AND (min_zoom IS NULL OR min_zoom <= $9) \
+                 AND (max_zoom IS NULL OR max_zoom >= $9) \
                  ORDER BY geom <-> ST_SetSRID(ST_MakePoint($6, $7), 0) \
                  LIMIT $8",
             )
@@ -135,14 +175,18 @@ impl MarkerRepo for PgMarkerRepo {
             .bind(cx)
             .bind(cy)
             .bind(limit)
+            .bind(q.zoom)
             .fetch_all(&self.pool)
             .await?
         } else {
             sqlx::query_as(
-                "SELECT id, category_id, ST_X(geom) AS x, ST_Y(geom) AS y, title \
+                "SELECT id, category_id, ST_X(geom) AS x, ST_Y(geom) AS y, title, \
+                        min_zoom, max_zoom \
                  FROM markers \
                  WHERE map_id = $1 AND category_id = ANY($2) \
                  AND geom && ST_MakeEnvelope($3, $4, $5, $6, 0) \
+                 AND (min_zoom IS NULL OR min_zoom <= $10) \
+                 AND (max_zoom IS NULL OR max_zoom >= $10) \
                  ORDER BY geom <-> ST_SetSRID(ST_MakePoint($7, $8), 0) \
                  LIMIT $9",
             )
@@ -155,6 +199,7 @@ impl MarkerRepo for PgMarkerRepo {
             .bind(cx)
             .bind(cy)
             .bind(limit)
+            .bind(q.zoom)
             .fetch_all(&self.pool)
             .await?
         };
@@ -163,15 +208,17 @@ impl MarkerRepo for PgMarkerRepo {
     }
 
     async fn map_meta(&self, map_id: i64) -> Result<Option<MapMeta>, RepoError> {
-        let row: Option<(i64, i64, i32)> =
-            sqlx::query_as("SELECT width, height, max_zoom FROM maps WHERE id = $1")
+        let row: Option<(i64, i64, i32, i32)> =
+            sqlx::query_as("SELECT width, height, max_zoom, tile_size FROM maps WHERE id = $1")
                 .bind(map_id)
                 .fetch_optional(&self.pool)
                 .await?;
-        Ok(row.map(|(width, height, max_zoom)| MapMeta {
+        Ok(row.map(|(width, height, max_zoom, tile_size)| MapMeta {
             width,
             height,
             max_zoom,
+            // A negative size can't tile anything; 0 gives an empty grid.
+            tile_size: u32::try_from(tile_size).unwrap_or(0),
         }))
     }
 
@@ -196,6 +243,8 @@ struct MarkerRow {
     x: f64,
     y: f64,
     title: Option<String>,
+    min_zoom: Option<i32>,
+    max_zoom: Option<i32>,
 }
 
 impl From<MarkerRow> for Marker {
@@ -206,6 +255,8 @@ impl From<MarkerRow> for Marker {
             x: r.x,
             y: r.y,
             title: r.title,
+            min_zoom: r.min_zoom,
+            max_zoom: r.max_zoom,
         }
     }
 }
@@ -267,7 +318,21 @@ impl InMemoryRepo {
         self.markers.iter().filter(move |m| {
             q.map_id == self.markers_map_id
                 && q.bbox.contains(m.x, m.y)
+                && m.visible_at(q.zoom)
                 && (q.categories.is_empty() || q.categories.contains(&m.category_id))
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_postgis_is_reported_with_the_fix() {
+        let err = require_postgis(None).unwrap_err();
+        assert!(matches!(err, RepoError::PostgisMissing));
+        assert!(err.to_string().contains("CREATE EXTENSION postgis"));
+        assert_eq!(require_postgis(Some("3.4.2".into())).unwrap(), "3.4.2");
+    }
+}
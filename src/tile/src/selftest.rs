@@ -0,0 +1,275 @@
+//! Optional startup self-test.
+//!
+//! Misconfigured wiring (wrong `DATABASE_URL`, PostGIS missing, a dead or
+//! mis-pointed `TILE_ORIGIN`) otherwise only shows up on the first user
+//! request. The self-test exercises each dependency once, end to end, through
+//! the same code the handlers use:
+//!
+//!   * **repo** — a real viewport count (`ST_MakeEnvelope` + `&&`) against map
+//!     id 0, which never exists (BIGSERIAL starts at 1), so it's cheap but still
+//!     fails if PostGIS isn't there.
+//!   * **tiles** — a cached read of a throwaway key, twice. The origin answering
+//!     "not found" is healthy (it's reachable); only an I/O error fails. The
+//!     second read must come back from the cache with the same answer.
+//!
+//! Each check has its own timeout so a hung dependency can't hang startup.
+//! `GET /readyz` runs [`readiness`] on demand: the same repo check, but only a
+//! [`TileOrigin::exists`] lookup (a HEAD on HTTP origins) for tiles, so a
+//! probe neither downloads bytes nor touches the cache or its disk tier.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::domain::{BBox, ViewportQuery};
+use crate::http::AppState;
+use crate::repo::MarkerRepo;
+use crate::tiles::{TileError, TileId, TileOrigin};
+
+/// Per-check budget.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Key prefix for the probe tile; not a real map.
+const PROBE_PREFIX: &str = "__selftest__";
+
+/// When to run the self-test at boot (`STARTUP_SELFTEST`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartupMode {
+    /// Don't run it (unset or empty).
+    #[default]
+    Off,
+    /// Run it and log a failure, but start anyway.
+    Warn,
+    /// Run it and refuse to start on failure.
+    Strict,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown mode {0:?} (expected off, warn or strict)")]
+pub struct UnknownStartupMode(pub String);
+
+impl FromStr for StartupMode {
+    type Err = UnknownStartupMode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "off" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "strict" => Ok(Self::Strict),
+            _ => Err(UnknownStartupMode(s.to_string())),
+        }
+    }
+}
+
+/// Outcome of one self-test run: `Err` carries a human-readable reason.
+#[derive(Debug)]
+pub struct SelfTestReport {
+    pub repo: Result<(), String>,
+    pub tiles: Result<(), String>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.repo.is_ok() && self.tiles.is_ok()
+    }
+}
+
+/// Run every check once and report (never panics, never fails fast).
+pub async fn run<R, O>(state: &AppState<R, O>) -> SelfTestReport
+where
+    R: MarkerRepo,
+    O: TileOrigin,
+{
+    SelfTestReport {
+        repo: with_timeout(check_repo(&state.repo)).await,
+        tiles: with_timeout(check_tiles(state)).await,
+    }
+}
+
+/// The cheap variant for `GET /readyz`, run every few seconds: repo check
+/// plus an origin existence lookup for the probe key.
+pub async fn readiness<R, O>(state: &AppState<R, O>) -> SelfTestReport
+where
+    R: MarkerRepo,
+    O: TileOrigin,
+{
+    SelfTestReport {
+        repo: with_timeout(check_repo(&state.repo)).await,
+        tiles: with_timeout(check_origin(state.tiles.origin())).await,
+    }
+}
+
+async fn with_timeout(
+    check: impl std::future::Future<Output = Result<(), String>>,
+) -> Result<(), String> {
+    tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| Err(format!("timed out after {CHECK_TIMEOUT:?}")))
+}
+
+async fn check_repo<R: MarkerRepo>(repo: &R) -> Result<(), String> {
+    let probe = ViewportQuery {
+        map_id: 0,
+        bbox: BBox::new(0.0, 0.0, 1.0, 1.0),
+        zoom: 0,
+        categories: Vec::new(),
+    };
+    repo.count_in_viewport(&probe)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+async fn check_tiles<R, O>(state: &AppState<R, O>) -> Result<(), String>
+where
+    R: MarkerRepo,
+    O: TileOrigin,
+{
+    let probe = probe_tile();
+    let first = state.tiles.get(probe.clone()).await;
+    if let Err(TileError::Io(e)) = &first {
+        return Err(format!("tile origin unreachable: {e}"));
+    }
+    let second = state.tiles.get(probe.clone()).await;
+    // Don't leave the probe cached; it isn't a real tile.
+    state.tiles.invalidate_tile(&probe, false).await;
+    match (&first, &second) {
+        (Ok(a), Ok(b)) if a == b => Ok(()),
+        (Err(TileError::NotFound), Err(TileError::NotFound)) => Ok(()),
+        _ => Err("tile cache returned a different result on re-read".into()),
+    }
+}
+
+/// Whether the origin answers for the probe key at all; "not stored" is
+/// healthy. Goes straight to the origin so a cached answer can't mask an
+/// outage.
+async fn check_origin<O: TileOrigin>(origin: &O) -> Result<(), String> {
+    match origin.exists(&probe_tile()).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("tile origin unreachable: {e}")),
+    }
+}
+
+fn probe_tile() -> TileId {
+    TileId {
+        prefix: PROBE_PREFIX.into(),
+        z: 0,
+        x: 0,
+        y: 0,
+        ext: "webp".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServeConfig;
+    use crate::domain::Marker;
+    use crate::http::tests::{app_state, state_with, EmptyOrigin};
+    use crate::http::SharedState;
+    use crate::repo::{MapMeta, RepoError};
+    use crate::tiles::CachedTiles;
+    use bytes::Bytes;
+
+    /// Origin whose backing store is down.
+    struct DownOrigin;
+    #[async_trait::async_trait]
+    impl TileOrigin for DownOrigin {
+        async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
+            Err(TileError::Io("connection refused".into()))
+        }
+    }
+
+    /// Repo whose database is down.
+    struct DownRepo;
+    #[async_trait::async_trait]
+    impl MarkerRepo for DownRepo {
+        async fn count_in_viewport(&self, _q: &ViewportQuery) -> Result<i64, RepoError> {
+            Err(RepoError::Db(sqlx::Error::PoolTimedOut))
+        }
+        async fn markers_in_viewport(
+            &self,
+            _q: &ViewportQuery,
+            _limit: i64,
+        ) -> Result<Vec<Marker>, RepoError> {
+            Err(RepoError::Db(sqlx::Error::PoolTimedOut))
+        }
+        async fn map_meta(&self, _map_id: i64) -> Result<Option<MapMeta>, RepoError> {
+            Err(RepoError::Db(sqlx::Error::PoolTimedOut))
+        }
+        async fn prefix_for_map(&self, _map_id: i64) -> Result<Option<String>, RepoError> {
+            Err(RepoError::Db(sqlx::Error::PoolTimedOut))
+        }
+    }
+
+    fn state<R: MarkerRepo, O: TileOrigin>(repo: R, origin: O) -> SharedState<R, O> {
+        app_state(
+            repo,
+            CachedTiles::new(origin, 1024 * 1024),
+            ServeConfig::default(),
+        )
+    }
+
+    #[test]
+    fn startup_mode_rejects_unknown_values() {
+        assert_eq!("".parse(), Ok(StartupMode::Off));
+        assert_eq!("off".parse(), Ok(StartupMode::Off));
+        assert_eq!("Warn".parse(), Ok(StartupMode::Warn));
+        assert_eq!(" strict ".parse(), Ok(StartupMode::Strict));
+        for garbage in ["true", "1", "on"] {
+            assert!(garbage.parse::<StartupMode>().is_err(), "{garbage}");
+        }
+    }
+
+    #[tokio::test]
+    async fn passes_against_a_healthy_harness() {
+        let report = run(&state_with(EmptyOrigin::default(), ServeConfig::default())).await;
+        assert!(report.passed(), "{report:?}");
+    }
+
+    #[tokio::test]
+    async fn fails_when_the_database_is_down() {
+        let report = run(&state(DownRepo, EmptyOrigin::default())).await;
+        assert!(!report.passed());
+        assert!(report.repo.is_err());
+        assert!(report.tiles.is_ok());
+    }
+
+    #[tokio::test]
+    async fn readiness_only_asks_the_origin_whether_the_probe_exists() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        /// Empty store that counts full reads.
+        #[derive(Default)]
+        struct Counting(AtomicUsize);
+        #[async_trait::async_trait]
+        impl TileOrigin for Counting {
+            async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Err(TileError::NotFound)
+            }
+            async fn exists(&self, _id: &TileId) -> Result<bool, TileError> {
+                Ok(false)
+            }
+        }
+        let state = state_with(Counting::default(), ServeConfig::default());
+        for _ in 0..3 {
+            let report = readiness(&state).await;
+            assert!(report.passed(), "{report:?}");
+        }
+        assert_eq!(state.tiles.origin().0.load(Ordering::SeqCst), 0);
+        state.tiles.run_pending_for_test().await;
+        assert_eq!(state.tiles.entry_count_for_test(), 0);
+
+        let report = readiness(&state_with(DownOrigin, ServeConfig::default())).await;
+        assert!(report.tiles.unwrap_err().contains("connection refused"));
+    }
+
+    #[tokio::test]
+    async fn fails_when_the_tile_origin_is_down() {
+        let report = run(&state_with(DownOrigin, ServeConfig::default())).await;
+        assert!(!report.passed());
+        assert!(report.repo.is_ok());
+        assert!(report.tiles.unwrap_err().contains("connection refused"));
+    }
+}
@@ -7,15 +7,22 @@
 //! requests for hot tiles (everyone opens the same starting area) don't hit the
 //! backing store.
 //!
-//! Two origins ship here: a local-filesystem one (matches the tiling pipeline's
+//! Two origins ship in `origin`: a local-filesystem one (matches the tiling pipeline's
 //! `LocalTileStore` layout, for dev) and an HTTP one (points at S3/MinIO/R2).
 
-use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use bytes::Bytes;
 use moka::future::Cache;
 
+use crate::grid::TileCoord;
+
+mod digest;
+mod origin;
+
+pub use digest::{DigestAlgorithm, UnknownDigest};
+pub use origin::{HttpTileOrigin, LocalTileOrigin, TieredTileOrigin};
+
 #[derive(Debug, thiserror::Error)]
 pub enum TileError {
     #[error("tile not found")]
@@ -51,94 +58,100 @@ impl TileId {
     }
 }
 
+/// A tile's bytes plus the integrity digest computed when it was fetched.
+///
+/// The digest is stored next to the bytes in the cache, so a cached response
+/// and a fresh one always carry the same value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tile {
+    pub bytes: Bytes,
+    /// `Digest` header value, e.g. `sha-256=<base64>` (RFC 3230).
+    pub digest: String,
+}
+
+impl Tile {
+    pub fn new(bytes: Bytes, alg: DigestAlgorithm) -> Self {
+        let digest = alg.digest(&bytes);
+        Self { bytes, digest }
+    }
+}
+
 /// Anything that can produce tile bytes for a key.
 #[async_trait::async_trait]
 pub trait TileOrigin: Send + Sync + 'static {
     async fn get(&self, id: &TileId) -> Result<Bytes, TileError>;
-}
-
-/// Reads tiles from a directory tree on disk.
-pub struct LocalTileOrigin {
-    root: PathBuf,
-}
 
-impl LocalTileOrigin {
-    pub fn new(root: impl AsRef<Path>) -> Self {
-        Self {
-            root: root.as_ref().to_path_buf(),
+    /// Whether the tile is stored, without transferring its bytes. The
+    /// default falls back to [`TileOrigin::get`]; real origins override it
+    /// with a metadata lookup.
+    async fn exists(&self, id: &TileId) -> Result<bool, TileError> {
+        match self.get(id).await {
+            Ok(_) => Ok(true),
+            Err(TileError::NotFound) => Ok(false),
+            Err(e) => Err(e),
         }
     }
+
+    /// Forget any copies this origin keeps on the side under `prefix` (`""`
+    /// = all). A no-op for sources of truth; tiers such as
+    /// [`TieredTileOrigin`]'s disk clear themselves.
+    async fn evict_prefix(&self, _prefix: &str) {}
+
+    /// [`TileOrigin::evict_prefix`] for a single tile.
+    async fn evict(&self, _id: &TileId) {}
 }
 
-#[async_trait::async_trait]
-impl TileOrigin for LocalTileOrigin {
-    async fn get(&self, id: &TileId) -> Result<Bytes, TileError> {
-        let path = self.root.join(id.key());
-        match tokio::fs::read(&path).await {
-            Ok(b) => Ok(Bytes::from(b)),
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(TileError::NotFound),
-            Err(e) => Err(TileError::Io(e.to_string())),
-        }
-    }
+/// Cache footprint of one map prefix, from [`CachedTiles::prefix_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct PrefixStats {
+    /// Cached tiles (with bytes).
+    pub tiles: u64,
+    /// Cached misses (negative entries).
+    pub misses: u64,
+    /// Sum of cached tile sizes.
+    pub bytes: u64,
 }
 
-/// Fetches tiles over HTTP(S) from an object-store / CDN base URL.
-/// rustls (ring + bundled webpki roots, matching the sqlx TLS choice) handles
-/// https origins — production TILE_ORIGIN is an https R2/CDN URL; plain http
-/// still works for the on-box MinIO in docker-compose.
-pub struct HttpTileOrigin {
-    base_url: String,
-    client: hyper_util::client::legacy::Client<
-        hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>,
-        http_body_util::Empty<Bytes>,
-    >,
+/// How long a cached tile lives.
+const HIT_TTL: Duration = Duration::from_secs(3600);
+
+/// Default lifetime of a cached miss: the `TILE_MISS_MAX_AGE` default.
+const DEFAULT_MISS_TTL: Duration =
+    Duration::from_secs(crate::config::DEFAULT_TILE_MISS_MAX_AGE as u64);
+
+/// Per-entry lifetimes: hits for [`HIT_TTL`], misses for `miss`, so a tile
+/// that appears after a re-tile is picked up without waiting out the hour.
+struct TileExpiry {
+    miss: Duration,
 }
 
-impl HttpTileOrigin {
-    pub fn new(base_url: impl Into<String>) -> Self {
-        let https = hyper_rustls::HttpsConnectorBuilder::new()
-            .with_webpki_roots()
-            .https_or_http()
-            .enable_http1()
-            .build();
-        let client =
-            hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
-                .build(https);
-        Self {
-            base_url: base_url.into().trim_end_matches('/').to_string(),
-            client,
+impl TileExpiry {
+    fn ttl(&self, slot: &Option<Tile>) -> Duration {
+        match slot {
+            Some(_) => HIT_TTL,
+            None => self.miss,
         }
     }
 }
 
-#[async_trait::async_trait]
-impl TileOrigin for HttpTileOrigin {
-    async fn get(&self, id: &TileId) -> Result<Bytes, TileError> {
-        use http_body_util::BodyExt;
-
-        let url = format!("{}/{}", self.base_url, id.key());
-        let uri: hyper::Uri = url
-            .parse()
-            .map_err(|e| TileError::Io(format!("bad uri: {e}")))?;
-        let resp = self
-            .client
-            .get(uri)
-            .await
-            .map_err(|e| TileError::Io(e.to_string()))?;
-
-        match resp.status().as_u16() {
-            200 => {
-                let body = resp
-                    .into_body()
-                    .collect()
-                    .await
-                    .map_err(|e| TileError::Io(e.to_string()))?
-                    .to_bytes();
-                Ok(body)
-            }
-            404 => Err(TileError::NotFound),
-            other => Err(TileError::Io(format!("origin status {other}"))),
-        }
+impl moka::Expiry<TileId, Option<Tile>> for TileExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &TileId,
+        slot: &Option<Tile>,
+        _created_at: std::time::Instant,
+    ) -> Option<Duration> {
+        Some(self.ttl(slot))
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &TileId,
+        slot: &Option<Tile>,
+        _updated_at: std::time::Instant,
+        _remaining: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(self.ttl(slot))
     }
 }
 
@@ -151,40 +164,64 @@ impl TileOrigin for HttpTileOrigin {
 #[derive(Clone)]
 pub struct CachedTiles<O: TileOrigin> {
     origin: std::sync::Arc<O>,
-    hits: Cache<TileId, Option<Bytes>>,
+    hits: Cache<TileId, Option<Tile>>,
+    digest: DigestAlgorithm,
 }
 
 impl<O: TileOrigin> CachedTiles<O> {
     pub fn new(origin: O, max_bytes: u64) -> Self {
-        let hits = Cache::builder()
-            .max_capacity(max_bytes)
-            .weigher(|_k: &TileId, v: &Option<Bytes>| {
-                v.as_ref().map(|b| b.len() as u32).unwrap_or(64).max(1)
-            })
-            .time_to_live(Duration::from_secs(3600))
-            // Required for `invalidate_prefix`: without this, moka rejects the
-            // `invalidate_entries_if` predicate (InvalidationClosuresDisabled).
-            .support_invalidation_closures()
-            .build();
         Self {
             origin: std::sync::Arc::new(origin),
-            hits,
+            hits: build_cache(max_bytes, DEFAULT_MISS_TTL),
+            digest: DigestAlgorithm::default(),
         }
     }
 
-    pub async fn get(&self, id: TileId) -> Result<Bytes, TileError> {
+    /// Keep cached misses for `ttl` instead of the default five minutes.
+    /// Call before serving: it starts from an empty cache.
+    pub fn with_miss_ttl(mut self, ttl: Duration) -> Self {
+        let max_bytes = self.hits.policy().max_capacity().unwrap_or(u64::MAX);
+        self.hits = build_cache(max_bytes, ttl);
+        self
+    }
+
+    /// Use `alg` for the `Digest` of tiles fetched from now on.
+    pub fn with_digest(mut self, alg: DigestAlgorithm) -> Self {
+        self.digest = alg;
+        self
+    }
+
+    /// The algorithm behind each tile's `Digest`.
+    pub fn digest_algorithm(&self) -> DigestAlgorithm {
+        self.digest
+    }
+
+    /// The backing origin, for checks that must not be answered (or left
+    /// behind) by the cache.
+    pub fn origin(&self) -> &O {
+        &self.origin
+    }
+
+    pub async fn get(&self, id: TileId) -> Result<Tile, TileError> {
+        // One debug line per read, keyed by `id.key()` (the cache key), so a
+        // misrouted tile can be traced with RUST_LOG=tile_service=debug; the
+        // origins log where that key resolved to (path or URI).
         if let Some(slot) = self.hits.get(&id).await {
+            tracing::debug!(key = %id.key(), cache = "hit", found = slot.is_some(), "tile read");
             return match slot {
-                Some(b) => Ok(b),
+                Some(t) => Ok(t),
                 None => Err(TileError::NotFound),
             };
         }
         match self.origin.get(&id).await {
             Ok(b) => {
-                self.hits.insert(id, Some(b.clone())).await;
-                Ok(b)
+                tracing::debug!(key = %id.key(), cache = "miss", found = true, "tile read");
+                let tile = Tile::new(b, self.digest);
+                self.hits.insert(id, Some(tile.clone())).await;
+                Ok(tile)
             }
             Err(TileError::NotFound) => {
+                tracing::debug!(key = %id.key(), cache = "miss", found = false, "tile read");
                 self.hits.insert(id, None).await; // negative cache
                 Err(TileError::NotFound)
             }
@@ -192,13 +229,46 @@ impl<O: TileOrigin> CachedTiles<O> {
         }
     }
 
+    /// Whether a tile exists, answered from the cache when it holds the key
+    /// (hit or cached miss) and otherwise by [`TileOrigin::exists`]. Never
+    /// fetches or caches bytes, so it is cheap to ask about many tiles.
+    pub async fn exists(&self, id: &TileId) -> Result<bool, TileError> {
+        match self.hits.get(id).await {
+            Some(slot) => Ok(slot.is_some()),
+            None => self.origin.exists(id).await,
+        }
+    }
+
+    /// What the cache currently holds under `prefix`. Walks every entry: the
+    /// cache is in-process and bounded by TILE_CACHE_MB, so that is a short
+    /// in-memory scan, not a network round trip per key.
+    pub async fn prefix_stats(&self, prefix: &str) -> PrefixStats {
+        self.hits.run_pending_tasks().await;
+        let mut stats = PrefixStats::default();
+        for (id, slot) in self.hits.iter() {
+            if id.prefix != prefix {
+                continue;
+            }
+            match slot {
+                Some(t) => {
+                    stats.tiles += 1;
+                    stats.bytes += t.bytes.len() as u64;
+                }
+                None => stats.misses += 1,
+            }
+        }
+        stats
+    }
+
     /// Drop every cached tile (positive or negative) under `prefix`.
     ///
     /// Called when the catalog signals a map changed: a re-tile rewrites the
     /// raster bytes under the same `<prefix>/z/x/y` keys, so the previously
     /// cached bytes are now stale. moka's `invalidate_entries_if` enqueues the
     /// predicate to run lazily against current entries; we don't await eviction.
-    pub fn invalidate_prefix(&self, prefix: &str) {
+    /// Origin-side tiers are cleared first, so memory can't refill from them.
+    pub async fn invalidate_prefix(&self, prefix: &str) {
+        self.origin.evict_prefix(prefix).await;
         let p = prefix.to_string();
         if let Err(e) = self
             .hits
@@ -210,6 +280,40 @@ impl<O: TileOrigin> CachedTiles<O> {
         }
     }
 
+    /// Drop one cached tile and, with `ancestors`, every lower-zoom tile whose
+    /// area contains it (up to zoom 0), so overview levels built from it
+    /// don't keep serving the old picture. Returns how many keys were
+    /// dropped, cached or not.
+    pub async fn invalidate_tile(&self, id: &TileId, ancestors: bool) -> u32 {
+        let mut id = id.clone();
+        let mut n = 0;
+        loop {
+            self.origin.evict(&id).await;
+            self.hits.invalidate(&id).await;
+            n += 1;
+            match TileCoord::new(id.z, id.x, id.y).parent() {
+                Some(p) if ancestors => (id.z, id.x, id.y) = (p.z, p.x, p.y),
+                _ => return n,
+            }
+        }
+    }
+
+    /// Drop every cached tile (all prefixes) and return how many entries were
+    /// live. For renderer/format changes that invalidate the whole pyramid.
+    /// `memory_only` leaves the origin's tiers (e.g. the disk tier) alone, to
+    /// shed memory without refetching every tile from the bucket.
+    pub async fn purge_all(&self, memory_only: bool) -> u64 {
+        if !memory_only {
+            self.origin.evict_prefix("").await;
+        }
+        // entry_count is only exact once deferred maintenance has run.
+        self.hits.run_pending_tasks().await;
+        let removed = self.hits.entry_count();
+        self.hits.invalidate_all();
+        self.hits.run_pending_tasks().await;
+        removed
+    }
+
     /// Test-only: force moka's deferred maintenance (insertions/invalidations)
     /// to run now, so assertions about cache contents are deterministic.
     #[cfg(any(test, feature = "memrepo"))]
@@ -224,117 +328,21 @@ impl<O: TileOrigin> CachedTiles<O> {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn tile_key_matches_pipeline_layout() {
-        let id = TileId {
-            prefix: "elden-ring/overworld".into(),
-            z: 4,
-            x: 3,
-            y: 7,
-            ext: "webp".into(),
-        };
-        assert_eq!(id.key(), "elden-ring/overworld/4/3/7.webp");
-        assert_eq!(id.mime(), "image/webp");
-    }
-
-    #[tokio::test]
-    async fn local_origin_reads_and_reports_missing() {
-        let dir = std::env::temp_dir().join(format!("tiles-test-{}", std::process::id()));
-        let key_dir = dir.join("m/4/3");
-        tokio::fs::create_dir_all(&key_dir).await.unwrap();
-        tokio::fs::write(key_dir.join("7.webp"), b"abc")
-            .await
-            .unwrap();
-
-        let origin = LocalTileOrigin::new(&dir);
-        let present = TileId {
-            prefix: "m".into(),
-            z: 4,
-            x: 3,
-            y: 7,
-            ext: "webp".into(),
-        };
-        let missing = TileId {
-            prefix: "m".into(),
-            z: 4,
-            x: 3,
-            y: 8,
-            ext: "webp".into(),
-        };
-
-        assert_eq!(&origin.get(&present).await.unwrap()[..], b"abc");
-        assert!(matches!(
-            origin.get(&missing).await,
-            Err(TileError::NotFound)
-        ));
-
-        let _ = tokio::fs::remove_dir_all(&dir).await;
-    }
-
-    #[tokio::test]
-    async fn cache_serves_second_hit_without_touching_origin() {
-        // A counting origin proves the second read is served from cache.
-        struct Counting {
-            n: std::sync::atomic::AtomicUsize,
-        }
-        #[async_trait::async_trait]
-        impl TileOrigin for Counting {
-            async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
-                self.n.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                Ok(Bytes::from_static(b"xyz"))
-            }
-        }
-        let origin = Counting {
-            n: Default::default(),
-        };
-        let cached = CachedTiles::new(origin, 1024 * 1024);
-        let id = TileId {
-            prefix: "m".into(),
-            z: 0,
-            x: 0,
-            y: 0,
-            ext: "webp".into(),
-        };
-
-        let a = cached.get(id.clone()).await.unwrap();
-        let b = cached.get(id.clone()).await.unwrap();
-        assert_eq!(a, b);
-        assert_eq!(cached.origin.n.load(std::sync::atomic::Ordering::SeqCst), 1);
-    }
-
-    #[tokio::test]
-    async fn invalidate_prefix_evicts_only_matching_prefix() {
-        struct Static;
-        #[async_trait::async_trait]
-        impl TileOrigin for Static {
-            async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
-                Ok(Bytes::from_static(b"x"))
-            }
-        }
-        let cached = CachedTiles::new(Static, 1024 * 1024);
-        let mk = |prefix: &str| TileId {
-            prefix: prefix.into(),
-            z: 0,
-            x: 0,
-            y: 0,
-            ext: "webp".into(),
-        };
-
-        // Prime two prefixes.
-        cached.get(mk("elden-ring/overworld")).await.unwrap();
-        cached.get(mk("other-map/world")).await.unwrap();
-        cached.hits.run_pending_tasks().await;
-        assert_eq!(cached.hits.entry_count(), 2);
-
-        // Invalidate one; the other survives.
-        cached.invalidate_prefix("elden-ring/overworld");
-        cached.hits.run_pending_tasks().await;
-        assert_eq!(cached.hits.entry_count(), 1);
-        assert!(cached.hits.get(&mk("other-map/world")).await.is_some());
-        assert!(cached.hits.get(&mk("elden-ring/overworld")).await.is_none());
-    }
+fn build_cache(max_bytes: u64, miss_ttl: Duration) -> Cache<TileId, Option<Tile>> {
+    Cache::builder()
+        .max_capacity(max_bytes)
+        .weigher(|_k: &TileId, v: &Option<Tile>| {
+            v.as_ref()
+                .map(|t| t.bytes.len() as u32)
+                .unwrap_or(64)
+                .max(1)
+        })
+        .expire_after(TileExpiry { miss: miss_ttl })
+        // Required for `invalidate_prefix`: without this, moka rejects the
+        // `invalidate_entries_if` predicate (InvalidationClosuresDisabled).
+        .support_invalidation_closures()
+        .build()
 }
+
+#[cfg(test)]
+mod tests;
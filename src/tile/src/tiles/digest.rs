@@ -0,0 +1,83 @@
+//! `Digest` header values (RFC 3230): `<algorithm>=<base64 hash>`.
+//!
+//! The algorithm is chosen once at startup (`TILE_DIGEST`) and applied when a
+//! tile is fetched from the origin; the value is cached with the bytes.
+
+use std::str::FromStr;
+
+use base64::Engine;
+use sha2::{Digest, Sha256, Sha512};
+
+/// Hash behind the `Digest` header, named as in the IANA digest registry.
+/// Both are cryptographic, so a digest doubles as an integrity check.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    #[default]
+    Sha256,
+    /// Faster than SHA-256 on 64-bit CPUs for anything but tiny inputs.
+    Sha512,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown digest algorithm {0:?} (expected sha-256 or sha-512)")]
+pub struct UnknownDigest(pub String);
+
+impl DigestAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha-256",
+            Self::Sha512 => "sha-512",
+        }
+    }
+
+    /// `<name>=<base64>` over `bytes`; deterministic across runs and hosts.
+    pub fn digest(self, bytes: &[u8]) -> String {
+        let hash = match self {
+            Self::Sha256 => Sha256::digest(bytes).to_vec(),
+            Self::Sha512 => Sha512::digest(bytes).to_vec(),
+        };
+        format!(
+            "{}={}",
+            self.name(),
+            base64::engine::general_purpose::STANDARD.encode(hash)
+        )
+    }
+}
+
+impl FromStr for DigestAlgorithm {
+    type Err = UnknownDigest;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "sha-256" => Ok(Self::Sha256),
+            "sha-512" => Ok(Self::Sha512),
+            _ => Err(UnknownDigest(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_algorithm_is_stable_and_distinct() {
+        // Known answers for "abc" (FIPS 180-2 test vectors, base64).
+        assert_eq!(
+            DigestAlgorithm::Sha256.digest(b"abc"),
+            "sha-256=ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0="
+        );
+        let sha512 = DigestAlgorithm::Sha512.digest(b"abc");
+        assert!(sha512.starts_with("sha-512=3a81oZNherrMQXNJriBBMRLm"));
+        assert_eq!(sha512, DigestAlgorithm::Sha512.digest(b"abc"));
+        assert_ne!(DigestAlgorithm::Sha512.digest(b"abd"), sha512);
+    }
+
+    #[test]
+    fn parses_registry_names_only() {
+        assert_eq!("sha-256".parse(), Ok(DigestAlgorithm::Sha256));
+        assert_eq!(" SHA-512 ".parse(), Ok(DigestAlgorithm::Sha512));
+        assert!("xxhash".parse::<DigestAlgorithm>().is_err());
+        assert!("sha256".parse::<DigestAlgorithm>().is_err());
+    }
+}
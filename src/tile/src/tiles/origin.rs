@@ -0,0 +1,474 @@
+//! The shipped [`TileOrigin`]s: local filesystem, HTTP object store, and a
+//! disk tier that can sit in front of either.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::{TileError, TileId, TileOrigin};
+
+/// Reads tiles from a directory tree on disk.
+pub struct LocalTileOrigin {
+    root: PathBuf,
+}
+
+impl LocalTileOrigin {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// On-disk path of `rel`, or `None` if it would escape `root` (the
+    /// prefix comes straight from the request path).
+    fn under_root(&self, rel: &str) -> Option<PathBuf> {
+        let rel = Path::new(rel);
+        rel.components()
+            .all(|c| matches!(c, Component::Normal(_)))
+            .then(|| self.root.join(rel))
+    }
+
+    /// Write a tile atomically (temp file + rename), creating directories.
+    /// Each call gets its own temp name, so concurrent writers of one tile
+    /// never interleave; the last rename wins with a complete file.
+    pub async fn put(&self, id: &TileId, bytes: &[u8]) -> Result<(), TileError> {
+        let io = |e: std::io::Error| TileError::Io(e.to_string());
+        let path = self
+            .under_root(&id.key())
+            .ok_or_else(|| TileError::Io(format!("unsafe tile key {:?}", id.key())))?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await.map_err(io)?;
+        }
+        static NEXT_TMP: AtomicU64 = AtomicU64::new(0);
+        let n = NEXT_TMP.fetch_add(1, Ordering::Relaxed);
+        let tmp = path.with_extension(format!("{}.tmp{}-{n}", id.ext, std::process::id()));
+        tokio::fs::write(&tmp, bytes).await.map_err(io)?;
+        tokio::fs::rename(&tmp, &path).await.map_err(io)
+    }
+
+    /// Delete one tile; missing is fine.
+    pub async fn remove(&self, id: &TileId) -> Result<(), TileError> {
+        let Some(path) = self.under_root(&id.key()) else {
+            return Ok(());
+        };
+        match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(TileError::Io(e.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Delete every tile under `prefix`; `""` empties the whole tree.
+    pub async fn remove_prefix(&self, prefix: &str) -> Result<(), TileError> {
+        let io = |e: std::io::Error| TileError::Io(e.to_string());
+        if prefix.is_empty() {
+            let mut entries = match tokio::fs::read_dir(&self.root).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(io(e)),
+            };
+            while let Some(entry) = entries.next_entry().await.map_err(io)? {
+                let path = entry.path();
+                if entry.file_type().await.map_err(io)?.is_dir() {
+                    tokio::fs::remove_dir_all(path).await.map_err(io)?;
+                } else {
+                    tokio::fs::remove_file(path).await.map_err(io)?;
+                }
+            }
+            return Ok(());
+        }
+        let Some(dir) = self.under_root(prefix) else {
+            return Ok(());
+        };
+        match tokio::fs::remove_dir_all(dir).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(io(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TileOrigin for LocalTileOrigin {
+    async fn get(&self, id: &TileId) -> Result<Bytes, TileError> {
+        let path = self.under_root(&id.key()).ok_or(TileError::NotFound)?;
+        tracing::debug!(path = %path.display(), "local origin read");
+        match tokio::fs::read(&path).await {
+            Ok(b) => Ok(Bytes::from(b)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(TileError::NotFound),
+            Err(e) => Err(TileError::Io(e.to_string())),
+        }
+    }
+
+    async fn exists(&self, id: &TileId) -> Result<bool, TileError> {
+        let Some(path) = self.under_root(&id.key()) else {
+            return Ok(false);
+        };
+        match tokio::fs::metadata(path).await {
+            Ok(m) => Ok(m.is_file()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(TileError::Io(e.to_string())),
+        }
+    }
+}
+
+/// Fetches tiles over HTTP(S) from an object-store / CDN base URL.
+/// rustls (ring + bundled webpki roots, matching the sqlx TLS choice) handles
+/// https origins — production TILE_ORIGIN is an https R2/CDN URL; plain http
+/// still works for the on-box MinIO in docker-compose.
+///
+/// Rate-limited upstreams answer 429/503 with a `Retry-After`; with
+/// [`HttpTileOrigin::with_retries`] those are waited out and retried rather
+/// than surfaced as origin errors.
+pub struct HttpTileOrigin {
+    base_url: String,
+    retries: u32,
+    client: hyper_util::client::legacy::Client<
+        hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>,
+        http_body_util::Empty<Bytes>,
+    >,
+}
+
+impl HttpTileOrigin {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let https = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        let client =
+            hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+                .build(https);
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            retries: 0,
+            client,
+        }
+    }
+
+    /// Retry 429/503 responses up to `retries` times (default 0).
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    fn uri(&self, id: &TileId) -> Result<hyper::Uri, TileError> {
+        format!("{}/{}", self.base_url, id.key())
+            .parse()
+            .map_err(|e| TileError::Io(format!("bad uri: {e}")))
+    }
+
+    async fn send(
+        &self,
+        method: hyper::Method,
+        id: &TileId,
+    ) -> Result<hyper::Response<hyper::body::Incoming>, TileError> {
+        let uri = self.uri(id)?;
+        tracing::debug!(%uri, %method, "http origin request");
+        let mut attempt = 0;
+        loop {
+            let req = hyper::Request::builder()
+                .method(method.clone())
+                .uri(uri.clone())
+                .body(http_body_util::Empty::new())
+                .map_err(|e| TileError::Io(e.to_string()))?;
+            let resp = self
+                .client
+                .request(req)
+                .await
+                .map_err(|e| TileError::Io(e.to_string()))?;
+            let throttled = matches!(resp.status().as_u16(), 429 | 503);
+            if !throttled || attempt >= self.retries {
+                return Ok(resp);
+            }
+            let Some(wait) = retry_after(resp.headers()) else {
+                return Ok(resp);
+            };
+            attempt += 1;
+            tracing::debug!(tile = %id.key(), status = resp.status().as_u16(), attempt,
+                wait_ms = wait.as_millis() as u64, "origin throttled; retrying");
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Longest `Retry-After` worth waiting inside a tile request; beyond it the
+/// client is better served by the error (and its own retry).
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(10);
+
+/// Wait requested by a throttled response: `Retry-After` in delta-seconds,
+/// 1s if absent or an HTTP-date, `None` if longer than [`MAX_RETRY_AFTER`].
+fn retry_after(headers: &hyper::HeaderMap) -> Option<Duration> {
+    let wait = headers
+        .get(hyper::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .map_or(Duration::from_secs(1), Duration::from_secs);
+    (wait <= MAX_RETRY_AFTER).then_some(wait)
+}
+
+#[async_trait::async_trait]
+impl TileOrigin for HttpTileOrigin {
+    async fn get(&self, id: &TileId) -> Result<Bytes, TileError> {
+        use http_body_util::BodyExt;
+
+        let resp = self.send(hyper::Method::GET, id).await?;
+        match resp.status().as_u16() {
+            200 => {
+                let body = resp
+                    .into_body()
+                    .collect()
+                    .await
+                    .map_err(|e| TileError::Io(e.to_string()))?
+                    .to_bytes();
+                Ok(body)
+            }
+            404 => Err(TileError::NotFound),
+            other => Err(TileError::Io(format!("origin status {other}"))),
+        }
+    }
+
+    /// A `HEAD` against the object store: status only, no body.
+    async fn exists(&self, id: &TileId) -> Result<bool, TileError> {
+        let resp = self.send(hyper::Method::HEAD, id).await?;
+        match resp.status().as_u16() {
+            200 => Ok(true),
+            404 => Ok(false),
+            other => Err(TileError::Io(format!("origin status {other}"))),
+        }
+    }
+}
+
+/// A local disk tier in front of another origin (`TILE_DISK_CACHE`).
+///
+/// Reads try the disk first; a miss goes upstream and the bytes are written
+/// back to disk (best effort), so a restarted instance starts warm instead of
+/// refetching everything from the bucket. The disk is only a cache: upstream
+/// is never written, and the `evict*` hooks clear the disk so a re-tile isn't
+/// masked by old bytes. Tiers nest, for longer chains.
+///
+/// The tier is unbounded (nothing expires or is trimmed), and a purge empties
+/// its whole root, so give it a dedicated directory on a volume sized for the
+/// full tile set.
+pub struct TieredTileOrigin<O: TileOrigin> {
+    disk: LocalTileOrigin,
+    upstream: O,
+}
+
+impl<O: TileOrigin> TieredTileOrigin<O> {
+    pub fn new(disk_root: impl AsRef<Path>, upstream: O) -> Self {
+        Self {
+            disk: LocalTileOrigin::new(disk_root),
+            upstream,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<O: TileOrigin> TileOrigin for TieredTileOrigin<O> {
+    async fn get(&self, id: &TileId) -> Result<Bytes, TileError> {
+        match self.disk.get(id).await {
+            Ok(b) => return Ok(b),
+            Err(TileError::NotFound) => {}
+            // A broken disk must not take serving down; go upstream.
+            Err(e) => tracing::warn!(error = %e, tile = %id.key(), "disk tier read failed"),
+        }
+        let bytes = self.upstream.get(id).await?;
+        if let Err(e) = self.disk.put(id, &bytes).await {
+            tracing::warn!(error = %e, tile = %id.key(), "disk tier write failed");
+        }
+        Ok(bytes)
+    }
+
+    async fn exists(&self, id: &TileId) -> Result<bool, TileError> {
+        if self.disk.exists(id).await.unwrap_or(false) {
+            return Ok(true);
+        }
+        self.upstream.exists(id).await
+    }
+
+    async fn evict_prefix(&self, prefix: &str) {
+        if let Err(e) = self.disk.remove_prefix(prefix).await {
+            tracing::error!(error = %e, prefix, "disk tier eviction failed");
+        }
+        self.upstream.evict_prefix(prefix).await;
+    }
+
+    async fn evict(&self, id: &TileId) {
+        if let Err(e) = self.disk.remove(id).await {
+            tracing::error!(error = %e, tile = %id.key(), "disk tier eviction failed");
+        }
+        self.upstream.evict(id).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn local_origin_reads_and_reports_missing() {
+        let dir = std::env::temp_dir().join(format!("tiles-test-{}", std::process::id()));
+        let key_dir = dir.join("m/4/3");
+        tokio::fs::create_dir_all(&key_dir).await.unwrap();
+        tokio::fs::write(key_dir.join("7.webp"), b"abc")
+            .await
+            .unwrap();
+
+        let origin = LocalTileOrigin::new(&dir);
+        let present = TileId {
+            prefix: "m".into(),
+            z: 4,
+            x: 3,
+            y: 7,
+            ext: "webp".into(),
+        };
+        let missing = TileId {
+            prefix: "m".into(),
+            z: 4,
+            x: 3,
+            y: 8,
+            ext: "webp".into(),
+        };
+
+        assert_eq!(&origin.get(&present).await.unwrap()[..], b"abc");
+        assert!(matches!(
+            origin.get(&missing).await,
+            Err(TileError::NotFound)
+        ));
+        assert!(origin.exists(&present).await.unwrap());
+        assert!(!origin.exists(&missing).await.unwrap());
+
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn concurrent_puts_of_one_tile_leave_a_complete_file() {
+        let dir = std::env::temp_dir().join(format!("tiles-put-test-{}", std::process::id()));
+        let origin = std::sync::Arc::new(LocalTileOrigin::new(&dir));
+        let id = TileId {
+            prefix: "m".into(),
+            z: 1,
+            x: 0,
+            y: 0,
+            ext: "webp".into(),
+        };
+        let writers: Vec<_> = (0..16u8)
+            .map(|i| {
+                let (origin, id) = (std::sync::Arc::clone(&origin), id.clone());
+                tokio::spawn(async move { origin.put(&id, &[i; 4096]).await })
+            })
+            .collect();
+        for w in writers {
+            w.await.unwrap().unwrap();
+        }
+        let bytes = origin.get(&id).await.unwrap();
+        assert_eq!(bytes.len(), 4096);
+        assert!(bytes.iter().all(|b| *b == bytes[0]));
+
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn tiered_origin_fills_the_disk_tier_and_evicts_it() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        /// Upstream holding every tile; counts fetches.
+        struct Bucket(Arc<AtomicUsize>);
+        #[async_trait::async_trait]
+        impl TileOrigin for Bucket {
+            async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(Bytes::from_static(b"from-bucket"))
+            }
+        }
+        let dir = std::env::temp_dir().join(format!("tiles-tiered-{}", std::process::id()));
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let tile = |prefix: &str| TileId {
+            prefix: prefix.into(),
+            z: 2,
+            x: 1,
+            y: 3,
+            ext: "webp".into(),
+        };
+
+        let tiered = TieredTileOrigin::new(&dir, Bucket(Arc::clone(&fetches)));
+        assert_eq!(&tiered.get(&tile("m")).await.unwrap()[..], b"from-bucket");
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(LocalTileOrigin::new(&dir).exists(&tile("m")).await.unwrap());
+
+        // A fresh instance over the same disk (a restart) serves from disk.
+        let tiered = TieredTileOrigin::new(&dir, Bucket(Arc::clone(&fetches)));
+        assert_eq!(&tiered.get(&tile("m")).await.unwrap()[..], b"from-bucket");
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        tiered.evict_prefix("m").await;
+        assert!(!LocalTileOrigin::new(&dir).exists(&tile("m")).await.unwrap());
+
+        // Keys that would escape the disk root are neither read nor written.
+        assert!(tiered.get(&tile("../escape")).await.is_ok());
+        assert!(!dir.join("../escape").exists());
+        assert!(LocalTileOrigin::new(&dir)
+            .put(&tile("../escape"), b"x")
+            .await
+            .is_err());
+
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn http_origin_waits_out_a_429_then_serves() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        use axum::http::{header, StatusCode};
+        use axum::response::IntoResponse;
+
+        // Upstream that throttles every first request, then serves.
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+        let upstream = axum::Router::new().fallback(move || {
+            let counter = Arc::clone(&counter);
+            async move {
+                if counter.fetch_add(1, Ordering::SeqCst).is_multiple_of(2) {
+                    (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, "0")]).into_response()
+                } else {
+                    "tile-bytes".into_response()
+                }
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+
+        let id = TileId {
+            prefix: "m".into(),
+            z: 0,
+            x: 0,
+            y: 0,
+            ext: "webp".into(),
+        };
+        let base = format!("http://{addr}");
+        // Without retries the 429 is an origin error.
+        assert!(matches!(
+            HttpTileOrigin::new(&base).get(&id).await,
+            Err(TileError::Io(_))
+        ));
+        hits.store(0, Ordering::SeqCst);
+
+        let origin = HttpTileOrigin::new(&base).with_retries(2);
+        assert_eq!(&origin.get(&id).await.unwrap()[..], b"tile-bytes");
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn retry_after_defaults_and_caps() {
+        let mut h = hyper::HeaderMap::new();
+        assert_eq!(retry_after(&h), Some(Duration::from_secs(1)));
+        h.insert(hyper::header::RETRY_AFTER, "3".parse().unwrap());
+        assert_eq!(retry_after(&h), Some(Duration::from_secs(3)));
+        h.insert(hyper::header::RETRY_AFTER, "3600".parse().unwrap());
+        assert_eq!(retry_after(&h), None);
+    }
+}
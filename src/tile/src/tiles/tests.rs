@@ -0,0 +1,253 @@
+//! Tests for the tile cache (split out to keep `tiles.rs` readable).
+
+use super::*;
+use crate::http::tests::StaticOrigin;
+
+#[test]
+fn tile_key_matches_pipeline_layout() {
+    let id = TileId {
+        prefix: "elden-ring/overworld".into(),
+        z: 4,
+        x: 3,
+        y: 7,
+        ext: "webp".into(),
+    };
+    assert_eq!(id.key(), "elden-ring/overworld/4/3/7.webp");
+    assert_eq!(id.mime(), "image/webp");
+}
+
+#[tokio::test]
+async fn cache_serves_second_hit_without_touching_origin() {
+    // A counting origin proves the second read is served from cache.
+    struct Counting {
+        n: std::sync::atomic::AtomicUsize,
+    }
+    #[async_trait::async_trait]
+    impl TileOrigin for Counting {
+        async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
+            self.n.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Bytes::from_static(b"xyz"))
+        }
+    }
+    let origin = Counting {
+        n: Default::default(),
+    };
+    let cached = CachedTiles::new(origin, 1024 * 1024);
+    let id = TileId {
+        prefix: "m".into(),
+        z: 0,
+        x: 0,
+        y: 0,
+        ext: "webp".into(),
+    };
+
+    let a = cached.get(id.clone()).await.unwrap();
+    let b = cached.get(id.clone()).await.unwrap();
+    assert_eq!(a, b);
+    assert_eq!(a.digest, DigestAlgorithm::Sha256.digest(b"xyz"));
+    assert_eq!(cached.origin.n.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn cached_misses_expire_after_the_miss_ttl() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Only column 0 exists; counts reads.
+    struct Counting(AtomicUsize);
+    #[async_trait::async_trait]
+    impl TileOrigin for Counting {
+        async fn get(&self, id: &TileId) -> Result<Bytes, TileError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            match id.x {
+                0 => Ok(Bytes::from_static(b"xyz")),
+                _ => Err(TileError::NotFound),
+            }
+        }
+    }
+    let cached = CachedTiles::new(Counting(AtomicUsize::new(0)), 1024 * 1024)
+        .with_miss_ttl(Duration::from_millis(50));
+    let tile = |x| TileId {
+        prefix: "m".into(),
+        z: 1,
+        x,
+        y: 0,
+        ext: "webp".into(),
+    };
+
+    for _ in 0..2 {
+        cached.get(tile(0)).await.unwrap();
+        assert!(cached.get(tile(1)).await.is_err());
+    }
+    assert_eq!(cached.origin.0.load(Ordering::SeqCst), 2);
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    cached.get(tile(0)).await.unwrap();
+    assert!(cached.get(tile(1)).await.is_err());
+    // Only the miss went back to the origin.
+    assert_eq!(cached.origin.0.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn invalidate_prefix_evicts_only_matching_prefix() {
+    let cached = CachedTiles::new(StaticOrigin, 1024 * 1024);
+    let mk = |prefix: &str| TileId {
+        prefix: prefix.into(),
+        z: 0,
+        x: 0,
+        y: 0,
+        ext: "webp".into(),
+    };
+
+    // Prime two prefixes.
+    cached.get(mk("elden-ring/overworld")).await.unwrap();
+    cached.get(mk("other-map/world")).await.unwrap();
+    cached.hits.run_pending_tasks().await;
+    assert_eq!(cached.hits.entry_count(), 2);
+
+    // Invalidate one; the other survives.
+    cached.invalidate_prefix("elden-ring/overworld").await;
+    cached.hits.run_pending_tasks().await;
+    assert_eq!(cached.hits.entry_count(), 1);
+    assert!(cached.hits.get(&mk("other-map/world")).await.is_some());
+    assert!(cached.hits.get(&mk("elden-ring/overworld")).await.is_none());
+}
+
+#[tokio::test]
+async fn invalidate_tile_can_walk_up_to_zoom_zero() {
+    let cached = CachedTiles::new(StaticOrigin, 1024 * 1024);
+    let at = |c: TileCoord| TileId {
+        prefix: "m".into(),
+        z: c.z,
+        x: c.x,
+        y: c.y,
+        ext: "webp".into(),
+    };
+    let changed = TileCoord::new(10, 700, 301);
+    let sibling = TileCoord::new(10, 701, 301);
+    let elsewhere = TileCoord::new(9, 0, 0);
+
+    let mut lineage = vec![changed];
+    while let Some(p) = lineage.last().unwrap().parent() {
+        lineage.push(p);
+    }
+    assert_eq!(lineage.len(), 11); // z10..=z0
+    for c in lineage.iter().chain([&sibling, &elsewhere]) {
+        cached.get(at(*c)).await.unwrap();
+    }
+
+    // Without the flag only the tile itself goes.
+    assert_eq!(cached.invalidate_tile(&at(changed), false).await, 1);
+    cached.hits.run_pending_tasks().await;
+    assert_eq!(cached.hits.entry_count(), 12);
+
+    cached.get(at(changed)).await.unwrap();
+    assert_eq!(cached.invalidate_tile(&at(changed), true).await, 11);
+    cached.hits.run_pending_tasks().await;
+    for c in &lineage {
+        assert!(cached.hits.get(&at(*c)).await.is_none(), "{c:?}");
+    }
+    assert!(cached.hits.get(&at(sibling)).await.is_some());
+    assert!(cached.hits.get(&at(elsewhere)).await.is_some());
+}
+
+#[tokio::test]
+async fn exists_never_downloads_tile_bytes() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Stores only `m/0/0/0.webp`; counts full reads.
+    #[derive(Default)]
+    struct OneTile {
+        gets: AtomicUsize,
+    }
+    #[async_trait::async_trait]
+    impl TileOrigin for OneTile {
+        async fn get(&self, _id: &TileId) -> Result<Bytes, TileError> {
+            self.gets.fetch_add(1, Ordering::SeqCst);
+            Ok(Bytes::from_static(b"x"))
+        }
+        async fn exists(&self, id: &TileId) -> Result<bool, TileError> {
+            Ok(id.key() == "m/0/0/0.webp")
+        }
+    }
+    let cached = CachedTiles::new(OneTile::default(), 1024 * 1024);
+    let at = |x| TileId {
+        prefix: "m".into(),
+        z: 0,
+        x,
+        y: 0,
+        ext: "webp".into(),
+    };
+
+    assert!(cached.exists(&at(0)).await.unwrap());
+    assert!(!cached.exists(&at(1)).await.unwrap());
+    assert_eq!(cached.origin.gets.load(Ordering::SeqCst), 0);
+    cached.hits.run_pending_tasks().await;
+    assert_eq!(cached.hits.entry_count(), 0);
+}
+
+#[tokio::test]
+async fn reads_log_the_cache_and_storage_keys_at_debug() {
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+    impl std::io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let capture = Capture::default();
+    let writer = capture.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    // Current-thread test runtime: the guard covers every await below.
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let dir = std::env::temp_dir().join(format!("tiles-log-test-{}", std::process::id()));
+    let file = dir.join("elden-ring/overworld/3/2/1.webp");
+    std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+    std::fs::write(&file, b"x").unwrap();
+    let cached = CachedTiles::new(LocalTileOrigin::new(&dir), 1024 * 1024);
+    let id = TileId {
+        prefix: "elden-ring/overworld".into(),
+        z: 3,
+        x: 2,
+        y: 1,
+        ext: "webp".into(),
+    };
+    cached.get(id.clone()).await.unwrap();
+    cached.get(id).await.unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+
+    let logs = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = logs.lines().filter(|l| l.contains("tile read")).collect();
+    assert_eq!(lines.len(), 2, "{logs}");
+    for (line, outcome) in lines.iter().zip(["cache=\"miss\"", "cache=\"hit\""]) {
+        assert!(line.contains("DEBUG"), "{line}");
+        assert!(
+            line.contains("key=elden-ring/overworld/3/2/1.webp"),
+            "{line}"
+        );
+        assert!(line.contains(outcome), "{line}");
+    }
+    // Only the miss reached storage, and it logged the resolved path.
+    let reads: Vec<&str> = logs
+        .lines()
+        .filter(|l| l.contains("local origin read"))
+        .collect();
+    assert_eq!(reads.len(), 1, "{logs}");
+    assert!(reads[0].contains("DEBUG"), "{}", reads[0]);
+    assert!(
+        reads[0].contains(&file.display().to_string()),
+        "{}",
+        reads[0]
+    );
+}
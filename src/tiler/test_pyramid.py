@@ -0,0 +1,49 @@
+"""Pyramid planning and edge handling at a non-power-of-two tile size."""
+from __future__ import annotations
+
+from PIL import Image
+
+from pyramid import compute_max_zoom, generate_tiles, plan_pyramid
+
+RED = (255, 0, 0, 255)
+CLEAR = (0, 0, 0, 0)
+
+
+def test_200px_tiles_count_and_pad_the_partial_edges():
+    # 1050x450 at 200px: 5.25 x 2.25 tiles at native size, so the last
+    # column is 50px wide and the last row 50px tall.
+    assert compute_max_zoom(1050, 450, 200) == 3
+    spec = plan_pyramid(1050, 450, tile_size=200)
+    assert spec.max_zoom == 3
+    # Levels 1050x450, 525x225, 263x113, 132x57, each rounded up to tiles.
+    assert [spec.grid_dimensions(z) for z in spec.zoom_range()] == [
+        (1, 1),
+        (2, 1),
+        (3, 2),
+        (6, 3),
+    ]
+    assert spec.tile_count() == 1 + 2 + 6 + 18
+
+    source = Image.new("RGBA", (1050, 450), RED)
+    tiles = {
+        (t.z, t.x, t.y): t.image
+        for t in generate_tiles(source, spec, skip_blank=False)
+    }
+    assert len(tiles) == spec.tile_count()
+    assert all(img.size == (200, 200) for img in tiles.values())
+
+    # Full tiles keep their last pixel column/row: no off-by-one crop.
+    full = tiles[(3, 4, 1)]
+    assert full.getpixel((199, 199)) == RED
+
+    # The corner tile holds the 50x50 remainder, padded with transparency.
+    corner = tiles[(3, 5, 2)]
+    assert corner.getpixel((0, 0)) == RED
+    assert corner.getpixel((49, 49)) == RED
+    assert corner.getpixel((50, 0)) == CLEAR
+    assert corner.getpixel((0, 50)) == CLEAR
+    assert corner.getpixel((199, 199)) == CLEAR
+
+    # Nothing is emitted past the grid.
+    assert (3, 6, 0) not in tiles
+    assert (3, 0, 3) not in tiles